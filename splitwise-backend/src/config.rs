@@ -5,21 +5,118 @@ use log::LevelFilter;
 #[derive(Debug, Deserialize, Clone)]
 pub struct Config {
     pub database_url: String,
-    pub jwt_secret: String,
+    pub jwt_secret: Option<String>,
+    pub jwt_algorithm: String,
+    pub jwt_signing_key_id: Option<String>,
+    pub jwt_signing_key_file: Option<String>,
+    pub jwt_jwks_file: Option<String>,
     pub port: u16,
     pub host: String,
+    pub public_url: String,
+    pub trust_proxy_headers: bool,
+    pub oidc: Option<OidcConfig>,
+    pub blob_store: String,
+    pub blob_store_path: String,
+    pub s3: Option<S3Config>,
+    pub recurring_expense_interval_secs: u64,
+    pub idempotency_key_ttl_secs: u64,
+    pub login_attempt_retention_days: i32,
+}
+
+/// Bucket settings for `BLOB_STORE=s3`. `endpoint` may point at any
+/// S3-compatible service; objects are addressed path-style.
+#[derive(Debug, Deserialize, Clone)]
+pub struct S3Config {
+    pub endpoint: String,
+    pub bucket: String,
+    pub region: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+}
+
+impl S3Config {
+    fn from_env() -> Option<Self> {
+        Some(S3Config {
+            endpoint: env::var("S3_ENDPOINT").ok()?,
+            bucket: env::var("S3_BUCKET").ok()?,
+            region: env::var("S3_REGION").unwrap_or_else(|_| "us-east-1".to_string()),
+            access_key_id: env::var("S3_ACCESS_KEY_ID").ok()?,
+            secret_access_key: env::var("S3_SECRET_ACCESS_KEY").ok()?,
+        })
+    }
+}
+
+/// External identity provider used for "Sign in with ..." logins. Endpoints
+/// are configured explicitly rather than discovered, so a local mock IdP
+/// works without TLS or a discovery document.
+#[derive(Debug, Deserialize, Clone)]
+pub struct OidcConfig {
+    pub provider: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub userinfo_endpoint: String,
+    pub redirect_uri: String,
+    pub scopes: String,
+}
+
+impl OidcConfig {
+    /// Enabled when `OIDC_CLIENT_ID` is set; the endpoints are then required.
+    fn from_env() -> Result<Option<Self>, env::VarError> {
+        let client_id = match env::var("OIDC_CLIENT_ID") {
+            Ok(client_id) => client_id,
+            Err(_) => return Ok(None),
+        };
+        Ok(Some(OidcConfig {
+            provider: env::var("OIDC_PROVIDER").unwrap_or_else(|_| "oidc".to_string()),
+            client_id,
+            client_secret: env::var("OIDC_CLIENT_SECRET").ok(),
+            authorization_endpoint: env::var("OIDC_AUTHORIZATION_ENDPOINT")?,
+            token_endpoint: env::var("OIDC_TOKEN_ENDPOINT")?,
+            userinfo_endpoint: env::var("OIDC_USERINFO_ENDPOINT")?,
+            redirect_uri: env::var("OIDC_REDIRECT_URI")?,
+            scopes: env::var("OIDC_SCOPES").unwrap_or_else(|_| "openid email profile".to_string()),
+        }))
+    }
 }
 
 impl Config {
     pub fn from_env() -> Result<Self, env::VarError> {
+        let port: u16 = env::var("PORT")
+            .unwrap_or_else(|_| "8080".to_string())
+            .parse()
+            .expect("PORT must be a number");
+        let host = env::var("HOST").unwrap_or_else(|_| "127.0.0.1".to_string());
         Ok(Config {
             database_url: env::var("DATABASE_URL")?,
-            jwt_secret: env::var("JWT_SECRET")?,
-            port: env::var("PORT")
-                .unwrap_or_else(|_| "8080".to_string())
-                .parse()
-                .expect("PORT must be a number"),
-            host: env::var("HOST").unwrap_or_else(|_| "127.0.0.1".to_string()),
+            jwt_secret: env::var("JWT_SECRET").ok(),
+            jwt_algorithm: env::var("JWT_ALGORITHM").unwrap_or_else(|_| "HS256".to_string()),
+            jwt_signing_key_id: env::var("JWT_SIGNING_KEY_ID").ok(),
+            jwt_signing_key_file: env::var("JWT_SIGNING_KEY_FILE").ok(),
+            jwt_jwks_file: env::var("JWT_JWKS_FILE").ok(),
+            public_url: env::var("PUBLIC_URL").unwrap_or_else(|_| format!("http://{}:{}", host, port)),
+            trust_proxy_headers: env::var("TRUST_PROXY_HEADERS")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(false),
+            oidc: OidcConfig::from_env()?,
+            blob_store: env::var("BLOB_STORE").unwrap_or_else(|_| "local".to_string()),
+            blob_store_path: env::var("BLOB_STORE_PATH").unwrap_or_else(|_| "uploads".to_string()),
+            s3: S3Config::from_env(),
+            recurring_expense_interval_secs: env::var("RECURRING_EXPENSE_INTERVAL_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(300),
+            idempotency_key_ttl_secs: env::var("IDEMPOTENCY_KEY_TTL_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(86400),
+            login_attempt_retention_days: env::var("LOGIN_ATTEMPT_RETENTION_DAYS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(30),
+            port,
+            host,
         })
     }
 }
@@ -0,0 +1,5 @@
+-- Migration: Token versioning
+-- Up
+
+-- Embedded in every JWT; bumping it revokes all tokens issued before the bump
+ALTER TABLE users ADD COLUMN IF NOT EXISTS token_version INTEGER NOT NULL DEFAULT 0;
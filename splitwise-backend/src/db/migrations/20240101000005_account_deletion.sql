@@ -0,0 +1,6 @@
+-- Migration: Account deletion
+-- Up
+
+-- Deleted accounts are anonymized in place rather than removed, because
+-- expenses.paid_by and splits.user_id must keep pointing at a row
+ALTER TABLE users ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMP WITH TIME ZONE;
@@ -0,0 +1,16 @@
+-- Migration: Login attempt tracking
+-- Up
+
+-- Every password/2FA attempt, kept as an audit trail of suspicious activity.
+-- outcome is one of 'success', 'failure' or 'blocked' (rejected while locked out).
+CREATE TABLE IF NOT EXISTS login_attempts (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    email VARCHAR(255) NOT NULL,
+    ip_address VARCHAR(45),
+    user_id UUID REFERENCES users(id) ON DELETE SET NULL,
+    outcome VARCHAR(20) NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_login_attempts_email_created_at ON login_attempts(email, created_at);
+CREATE INDEX IF NOT EXISTS idx_login_attempts_ip_created_at ON login_attempts(ip_address, created_at);
@@ -0,0 +1,6 @@
+-- Migration: User search
+-- Up
+
+-- Backs the name prefix and exact email lookups of GET /api/users/search
+CREATE INDEX IF NOT EXISTS idx_users_lower_name ON users (LOWER(name) text_pattern_ops);
+CREATE INDEX IF NOT EXISTS idx_users_lower_email ON users (LOWER(email));
@@ -0,0 +1,14 @@
+-- Migration: User preferences
+-- Up
+
+-- One row per user, created on first save; missing rows mean the defaults
+CREATE TABLE IF NOT EXISTS user_preferences (
+    user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    currency CHAR(3) NOT NULL DEFAULT 'USD',
+    locale VARCHAR(35) NOT NULL DEFAULT 'en-US',
+    timezone VARCHAR(64) NOT NULL DEFAULT 'UTC',
+    date_format VARCHAR(20) NOT NULL DEFAULT 'YYYY-MM-DD',
+    notify_group_activity BOOLEAN NOT NULL DEFAULT TRUE,
+    notify_expense_activity BOOLEAN NOT NULL DEFAULT TRUE,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
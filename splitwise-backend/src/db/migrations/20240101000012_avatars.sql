@@ -0,0 +1,6 @@
+-- Migration: Avatars
+-- Up
+
+-- Blob store key prefix of the current picture; NULL when none is set
+ALTER TABLE users ADD COLUMN IF NOT EXISTS avatar_key VARCHAR(255);
+ALTER TABLE groups ADD COLUMN IF NOT EXISTS avatar_key VARCHAR(255);
@@ -0,0 +1,62 @@
+-- Migration: Expense categories and keyword rules
+-- Up
+
+-- Predefined categories have no group; custom ones belong to a single group
+CREATE TABLE IF NOT EXISTS categories (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    group_id UUID REFERENCES groups(id) ON DELETE CASCADE,
+    name VARCHAR(50) NOT NULL,
+    created_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_categories_predefined_name
+    ON categories (LOWER(name)) WHERE group_id IS NULL;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_categories_group_name
+    ON categories (group_id, LOWER(name)) WHERE group_id IS NOT NULL;
+
+INSERT INTO categories (name) VALUES
+    ('General'), ('Food'), ('Groceries'), ('Transport'), ('Travel'), ('Rent'),
+    ('Utilities'), ('Entertainment'), ('Shopping'), ('Health')
+ON CONFLICT DO NOTHING;
+
+ALTER TABLE expenses ADD COLUMN IF NOT EXISTS category_id UUID REFERENCES categories(id) ON DELETE SET NULL;
+CREATE INDEX IF NOT EXISTS idx_expenses_category_id ON expenses(category_id);
+
+-- Keywords matched against expense descriptions. Built-in rules have no group;
+-- a group's own rules take precedence over them.
+CREATE TABLE IF NOT EXISTS category_rules (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    group_id UUID REFERENCES groups(id) ON DELETE CASCADE,
+    keyword VARCHAR(50) NOT NULL,
+    category_id UUID NOT NULL REFERENCES categories(id) ON DELETE CASCADE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_category_rules_builtin_keyword
+    ON category_rules (keyword) WHERE group_id IS NULL;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_category_rules_group_keyword
+    ON category_rules (group_id, keyword) WHERE group_id IS NOT NULL;
+
+INSERT INTO category_rules (keyword, category_id)
+SELECT rule.keyword, c.id
+FROM (VALUES
+    ('uber', 'Transport'), ('ola', 'Transport'), ('lyft', 'Transport'), ('taxi', 'Transport'),
+    ('cab', 'Transport'), ('metro', 'Transport'), ('bus', 'Transport'), ('train', 'Transport'),
+    ('fuel', 'Transport'), ('petrol', 'Transport'), ('parking', 'Transport'),
+    ('swiggy', 'Food'), ('zomato', 'Food'), ('restaurant', 'Food'), ('lunch', 'Food'),
+    ('dinner', 'Food'), ('breakfast', 'Food'), ('pizza', 'Food'), ('coffee', 'Food'),
+    ('cafe', 'Food'), ('drinks', 'Food'),
+    ('groceries', 'Groceries'), ('grocery', 'Groceries'), ('supermarket', 'Groceries'),
+    ('bigbasket', 'Groceries'), ('blinkit', 'Groceries'),
+    ('flight', 'Travel'), ('hotel', 'Travel'), ('airbnb', 'Travel'), ('hostel', 'Travel'),
+    ('rent', 'Rent'),
+    ('electricity', 'Utilities'), ('water', 'Utilities'), ('internet', 'Utilities'),
+    ('wifi', 'Utilities'), ('gas', 'Utilities'),
+    ('movie', 'Entertainment'), ('movies', 'Entertainment'), ('netflix', 'Entertainment'),
+    ('concert', 'Entertainment'), ('tickets', 'Entertainment'),
+    ('amazon', 'Shopping'), ('flipkart', 'Shopping'),
+    ('pharmacy', 'Health'), ('doctor', 'Health'), ('medicine', 'Health'), ('gym', 'Health')
+) AS rule(keyword, category)
+JOIN categories c ON c.group_id IS NULL AND c.name = rule.category
+ON CONFLICT DO NOTHING;
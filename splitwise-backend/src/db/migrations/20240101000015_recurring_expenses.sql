@@ -0,0 +1,36 @@
+-- Migration: Recurring expenses
+-- Up
+
+CREATE TABLE IF NOT EXISTS recurring_expenses (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    group_id UUID REFERENCES groups(id) ON DELETE CASCADE, -- NULL for direct expenses between friends
+    description TEXT NOT NULL,
+    amount DECIMAL(10,2) NOT NULL,
+    paid_by UUID NOT NULL REFERENCES users(id),
+    split_type VARCHAR(50) NOT NULL DEFAULT 'equal',
+    category_id UUID REFERENCES categories(id) ON DELETE SET NULL,
+    splits JSONB NOT NULL, -- [{"user_id": ..., "amount": ...}]
+    frequency VARCHAR(10) NOT NULL, -- "daily", "weekly", "monthly", "yearly"
+    start_date DATE NOT NULL,
+    end_date DATE,
+    -- Occurrence n falls on start_date + n periods; next_occurrence caches
+    -- occurrence `sequence` and is NULL once the schedule has ended
+    sequence INTEGER NOT NULL DEFAULT 0,
+    next_occurrence DATE,
+    is_paused BOOLEAN NOT NULL DEFAULT FALSE,
+    created_by UUID NOT NULL REFERENCES users(id),
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    CHECK (frequency IN ('daily', 'weekly', 'monthly', 'yearly')),
+    CHECK (end_date IS NULL OR end_date >= start_date)
+);
+
+CREATE INDEX IF NOT EXISTS idx_recurring_expenses_due
+    ON recurring_expenses(next_occurrence) WHERE NOT is_paused AND next_occurrence IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_recurring_expenses_group_id ON recurring_expenses(group_id);
+
+-- Each occurrence is materialized at most once, however often the scheduler retries
+ALTER TABLE expenses ADD COLUMN IF NOT EXISTS recurring_expense_id UUID REFERENCES recurring_expenses(id) ON DELETE SET NULL;
+ALTER TABLE expenses ADD COLUMN IF NOT EXISTS occurrence_date DATE;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_expenses_recurring_occurrence
+    ON expenses(recurring_expense_id, occurrence_date);
@@ -0,0 +1,16 @@
+-- Migration: Receipt attachments on expenses
+-- Up
+
+CREATE TABLE IF NOT EXISTS expense_attachments (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    expense_id UUID NOT NULL REFERENCES expenses(id) ON DELETE CASCADE,
+    uploaded_by UUID NOT NULL REFERENCES users(id),
+    file_name VARCHAR(255) NOT NULL,
+    content_type VARCHAR(100) NOT NULL, -- "image/jpeg" (images are re-encoded) or "application/pdf"
+    size_bytes INTEGER NOT NULL,
+    blob_key TEXT NOT NULL,
+    thumbnail_key TEXT, -- Images only
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_expense_attachments_expense_id ON expense_attachments(expense_id, created_at);
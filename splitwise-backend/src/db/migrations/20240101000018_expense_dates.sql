@@ -0,0 +1,29 @@
+-- Migration: Expense dates and group timezones
+-- Up
+
+-- Calendar dates of a group's expenses are in the group's timezone. Existing
+-- groups take their creator's.
+ALTER TABLE groups ADD COLUMN IF NOT EXISTS timezone VARCHAR(64) NOT NULL DEFAULT 'UTC';
+
+UPDATE groups g SET timezone = p.timezone
+FROM user_preferences p
+WHERE p.user_id = g.created_by;
+
+-- The day the expense happened, as opposed to when it was recorded.
+-- Existing expenses are dated by when they were recorded, or by their
+-- occurrence for ones created from a recurring expense.
+ALTER TABLE expenses ADD COLUMN IF NOT EXISTS expense_date DATE;
+
+UPDATE expenses e SET expense_date = COALESCE(
+    e.occurrence_date,
+    (e.created_at AT TIME ZONE COALESCE(
+        (SELECT g.timezone FROM groups g WHERE g.id = e.group_id),
+        (SELECT p.timezone FROM user_preferences p WHERE p.user_id = e.created_by),
+        'UTC'
+    ))::DATE
+)
+WHERE e.expense_date IS NULL;
+
+ALTER TABLE expenses ALTER COLUMN expense_date SET NOT NULL;
+
+CREATE INDEX IF NOT EXISTS idx_expenses_group_expense_date ON expenses(group_id, expense_date DESC);
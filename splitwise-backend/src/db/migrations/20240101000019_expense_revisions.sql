@@ -0,0 +1,15 @@
+-- Migration: Expense revision history
+-- Up
+
+-- One row per change to an expense, with a field-level diff. Rows are never
+-- edited; they go away only with the expense itself.
+CREATE TABLE IF NOT EXISTS expense_revisions (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    expense_id UUID NOT NULL REFERENCES expenses(id) ON DELETE CASCADE,
+    revision INTEGER NOT NULL, -- 1, 2, ... per expense
+    changed_by UUID NOT NULL REFERENCES users(id),
+    action VARCHAR(20) NOT NULL, -- "created", "updated", "deleted"
+    changes JSONB NOT NULL DEFAULT '[]', -- [{"field", "from", "to"}]
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    UNIQUE (expense_id, revision)
+);
@@ -0,0 +1,24 @@
+-- Migration: Full-text search over expenses, comments and groups
+-- Up
+
+ALTER TABLE expenses ADD COLUMN IF NOT EXISTS notes TEXT;
+
+-- Descriptions and names rank above notes and group descriptions
+ALTER TABLE expenses ADD COLUMN IF NOT EXISTS search_vector TSVECTOR
+    GENERATED ALWAYS AS (
+        setweight(to_tsvector('english', COALESCE(description, '')), 'A') ||
+        setweight(to_tsvector('english', COALESCE(notes, '')), 'B')
+    ) STORED;
+
+ALTER TABLE expense_comments ADD COLUMN IF NOT EXISTS search_vector TSVECTOR
+    GENERATED ALWAYS AS (to_tsvector('english', body)) STORED;
+
+ALTER TABLE groups ADD COLUMN IF NOT EXISTS search_vector TSVECTOR
+    GENERATED ALWAYS AS (
+        setweight(to_tsvector('english', COALESCE(name, '')), 'A') ||
+        setweight(to_tsvector('english', COALESCE(description, '')), 'B')
+    ) STORED;
+
+CREATE INDEX IF NOT EXISTS idx_expenses_search ON expenses USING GIN (search_vector);
+CREATE INDEX IF NOT EXISTS idx_expense_comments_search ON expense_comments USING GIN (search_vector);
+CREATE INDEX IF NOT EXISTS idx_groups_search ON groups USING GIN (search_vector);
@@ -0,0 +1,7 @@
+-- Migration: Versions for optimistic concurrency control
+-- Up
+
+-- Bumped on every change and exposed as the ETag, so an update or delete
+-- based on a stale copy can be refused
+ALTER TABLE expenses ADD COLUMN IF NOT EXISTS version INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE groups ADD COLUMN IF NOT EXISTS version INTEGER NOT NULL DEFAULT 1;
@@ -0,0 +1,9 @@
+-- Migration: Tie pending single sign-on logins to the client that started them
+-- Up
+
+-- Pending logins last minutes; drop any left over rather than backfill
+DELETE FROM oidc_login_states;
+
+-- Hash of the secret handed only to the client that called /authorize, so a
+-- `code` and `state` from someone else's login can't be completed elsewhere
+ALTER TABLE oidc_login_states ADD COLUMN IF NOT EXISTS login_secret_hash VARCHAR(64) NOT NULL;
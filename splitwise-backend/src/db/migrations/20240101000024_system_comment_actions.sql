@@ -0,0 +1,15 @@
+-- Migration: Keep the actor's name out of system comment bodies
+-- Up
+
+-- System comments used to be stored as "<name> <action>". Only the action is
+-- kept now and the name comes from the author, so it follows renames and
+-- account deletion. Strip the name the comment was written with: the
+-- author's current one where it still matches, otherwise everything up to
+-- the first known action.
+UPDATE expense_comments c
+SET body = CASE
+        WHEN left(c.body, length(u.name) + 1) = u.name || ' ' THEN substr(c.body, length(u.name) + 2)
+        ELSE regexp_replace(c.body, '^.*? (added this expense|changed |deleted this expense|attached |removed )', '\1')
+    END
+FROM users u
+WHERE u.id = c.user_id AND c.is_system;
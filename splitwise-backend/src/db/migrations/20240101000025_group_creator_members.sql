@@ -0,0 +1,12 @@
+-- Migration: Make group creators ordinary members
+-- Up
+
+-- Creators used to count as members through groups.created_by, so removing
+-- them from a group changed nothing. They now get a member row when the group
+-- is created; give existing creators theirs, except deleted accounts.
+INSERT INTO group_members (group_id, user_id, role)
+SELECT g.id, g.created_by, 'admin'
+FROM groups g
+JOIN users u ON u.id = g.created_by
+WHERE u.deleted_at IS NULL
+ON CONFLICT (group_id, user_id) DO NOTHING;
@@ -0,0 +1,75 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::models::api_token::{ApiToken, ApiTokenResponse, Scope};
+use crate::utils::auth::authenticate;
+use crate::utils::error::AppError;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateApiTokenRequest {
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    pub expires_at: Option<DateTime<Utc>>, // Never expires when omitted
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreateApiTokenResponse {
+    #[serde(flatten)]
+    pub api_token: ApiTokenResponse,
+    pub token: String, // Only ever returned here
+}
+
+pub async fn create_api_token(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    body: web::Json<CreateApiTokenRequest>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authenticate(&req, &pool).await?;
+
+    let name = body.name.trim();
+    if name.is_empty() {
+        return Err(AppError::bad_request("Token name is required"));
+    }
+    if body.scopes.is_empty() {
+        return Err(AppError::bad_request("At least one scope is required"));
+    }
+    if body.expires_at.is_some_and(|expires_at| expires_at <= Utc::now()) {
+        return Err(AppError::bad_request("Expiry must be in the future"));
+    }
+
+    let mut scopes = body.scopes.clone();
+    scopes.sort();
+    scopes.dedup();
+    let (api_token, token) = ApiToken::create(&pool, user_id, name, &scopes, body.expires_at).await?;
+    Ok(HttpResponse::Created().json(CreateApiTokenResponse {
+        api_token: api_token.to_response(),
+        token,
+    }))
+}
+
+pub async fn list_api_tokens(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authenticate(&req, &pool).await?;
+    let tokens: Vec<ApiTokenResponse> = ApiToken::list_active(&pool, user_id)
+        .await?
+        .iter()
+        .map(ApiToken::to_response)
+        .collect();
+    Ok(HttpResponse::Ok().json(tokens))
+}
+
+pub async fn revoke_api_token(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authenticate(&req, &pool).await?;
+    if !ApiToken::revoke(&pool, user_id, path.into_inner()).await? {
+        return Err(AppError::not_found("API token not found"));
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,258 @@
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::models::api_token::Scope;
+use crate::models::attachment::{AttachmentResponse, ExpenseAttachment, NewAttachment};
+use crate::models::comment::ExpenseComment;
+use crate::models::expense::Expense;
+use crate::utils::auth::authorize;
+use crate::utils::blob_store::BlobStore;
+use crate::utils::error::AppError;
+use crate::utils::images::{process_receipt_image, PROCESSED_CONTENT_TYPE};
+use crate::utils::multipart::read_files;
+
+pub const MAX_ATTACHMENT_BYTES: usize = 10 * 1024 * 1024;
+pub const MAX_ATTACHMENTS_PER_EXPENSE: usize = 10;
+const MAX_FILES_PER_UPLOAD: usize = 5;
+const PDF_CONTENT_TYPE: &str = "application/pdf";
+const MAX_FILE_NAME_LEN: usize = 255;
+
+/// Request bodies of attachment uploads may be this large: 25 MB of files
+/// in total, plus room for the multipart framing around them.
+pub const MAX_ATTACHMENT_BODY_BYTES: usize = 25 * 1024 * 1024 + 64 * 1024;
+
+#[derive(Debug, Deserialize)]
+pub struct AttachmentQuery {
+    pub size: Option<String>, // "thumb" for an image's thumbnail
+}
+
+/// Best-effort cleanup of attachment blobs whose rows are gone. A failure
+/// only leaves an orphaned file behind, so it is logged rather than surfaced.
+pub async fn delete_attachment_blobs(blob_store: &dyn BlobStore, keys: Vec<String>) {
+    for key in keys {
+        if let Err(e) = blob_store.delete(&key).await {
+            log::warn!("Failed to delete attachment blob {}: {}", key, e);
+        }
+    }
+}
+
+async fn require_visible(pool: &PgPool, expense_id: Uuid, user_id: Uuid) -> Result<(), AppError> {
+    if !Expense::is_visible_to(pool, expense_id, user_id).await? || Expense::is_deleted(pool, expense_id).await? {
+        return Err(AppError::not_found("Expense not found"));
+    }
+    Ok(())
+}
+
+/// Keeps the last path component of a client-supplied name and drops
+/// characters that would need escaping in a Content-Disposition header.
+fn clean_file_name(file_name: Option<&str>, fallback: &str) -> String {
+    let name: String = file_name
+        .and_then(|name| name.rsplit(['/', '\\']).next())
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| !c.is_control() && *c != '"')
+        .take(MAX_FILE_NAME_LEN)
+        .collect();
+    let name = name.trim();
+    if name.is_empty() {
+        fallback.to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+/// Stores one uploaded file and records it. PDFs are kept as uploaded;
+/// images are re-encoded (dropping metadata) and get a thumbnail.
+async fn store_attachment(
+    pool: &PgPool,
+    blob_store: &dyn BlobStore,
+    expense_id: Uuid,
+    user_id: Uuid,
+    file_name: Option<&str>,
+    data: Vec<u8>,
+) -> Result<ExpenseAttachment, AppError> {
+    let id = Uuid::new_v4();
+    let prefix = format!("attachments/{}/{}", expense_id, id);
+    let (content_type, blobs, file_name) = if data.starts_with(b"%PDF-") {
+        let blobs = vec![(format!("{}/original.pdf", prefix), PDF_CONTENT_TYPE, data)];
+        (PDF_CONTENT_TYPE, blobs, clean_file_name(file_name, "receipt.pdf"))
+    } else {
+        let processed = web::block(move || process_receipt_image(&data))
+            .await
+            .map_err(|_| AppError::internal_error("Failed to process image"))?
+            .map_err(|_| AppError::bad_request("Attachments must be PNG, JPEG or WebP images, or PDFs"))?;
+        let blobs = vec![
+            (format!("{}/full.jpg", prefix), PROCESSED_CONTENT_TYPE, processed.full),
+            (format!("{}/thumb.jpg", prefix), PROCESSED_CONTENT_TYPE, processed.thumbnail),
+        ];
+        (PROCESSED_CONTENT_TYPE, blobs, clean_file_name(file_name, "receipt.jpg"))
+    };
+
+    let blob_key = blobs[0].0.clone();
+    let thumbnail_key = blobs.get(1).map(|(key, _, _)| key.clone());
+    let size_bytes = blobs[0].2.len() as i32;
+    for (key, content_type, data) in blobs {
+        blob_store.put(&key, content_type, data).await.map_err(|e| {
+            log::error!("Failed to store attachment {}: {}", key, e);
+            AppError::internal_error("Failed to store attachment")
+        })?;
+    }
+
+    let new = NewAttachment {
+        id,
+        expense_id,
+        uploaded_by: user_id,
+        file_name: &file_name,
+        content_type,
+        size_bytes,
+        blob_key: &blob_key,
+        thumbnail_key: thumbnail_key.as_deref(),
+    };
+    let result = ExpenseAttachment::create(pool, &new, MAX_ATTACHMENTS_PER_EXPENSE).await;
+    if !matches!(result, Ok(Some(_))) {
+        delete_attachment_blobs(blob_store, std::iter::once(blob_key).chain(thumbnail_key).collect()).await;
+    }
+    result?.ok_or_else(too_many_attachments)
+}
+
+fn too_many_attachments() -> AppError {
+    AppError::bad_request(&format!(
+        "An expense can have at most {} attachments",
+        MAX_ATTACHMENTS_PER_EXPENSE
+    ))
+}
+
+/// Attaches receipts to an expense: one or more `file` fields of a
+/// multipart/form-data body, each a PNG, JPEG or WebP image or a PDF.
+pub async fn upload_attachments(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    blob_store: web::Data<dyn BlobStore>,
+    path: web::Path<Uuid>,
+    body: web::Bytes,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authorize(&req, &pool, Scope::ExpensesWrite).await?;
+    let expense_id = path.into_inner();
+    require_visible(&pool, expense_id, user_id).await?;
+
+    let files = read_files(&req, body, "file", MAX_FILES_PER_UPLOAD, MAX_ATTACHMENT_BYTES).await?;
+    // Checked again, under a lock, as each file is recorded
+    let existing = ExpenseAttachment::count(&pool, expense_id).await? as usize;
+    if existing + files.len() > MAX_ATTACHMENTS_PER_EXPENSE {
+        return Err(too_many_attachments());
+    }
+
+    let mut attachments = Vec::with_capacity(files.len());
+    for file in files {
+        let attachment = store_attachment(
+            &pool,
+            blob_store.get_ref(),
+            expense_id,
+            user_id,
+            file.file_name.as_deref(),
+            file.data,
+        )
+        .await?;
+        ExpenseComment::add_system(pool.get_ref(), expense_id, user_id, &format!("attached {}", attachment.file_name))
+            .await?;
+        attachments.push(AttachmentResponse::from(attachment));
+    }
+    Ok(HttpResponse::Ok().json(attachments))
+}
+
+pub async fn list_attachments(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authorize(&req, &pool, Scope::Read).await?;
+    let expense_id = path.into_inner();
+    require_visible(&pool, expense_id, user_id).await?;
+    let attachments: Vec<AttachmentResponse> = ExpenseAttachment::list(&pool, expense_id)
+        .await?
+        .into_iter()
+        .map(AttachmentResponse::from)
+        .collect();
+    Ok(HttpResponse::Ok().json(attachments))
+}
+
+pub async fn get_attachment(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    blob_store: web::Data<dyn BlobStore>,
+    path: web::Path<(Uuid, Uuid)>,
+    query: web::Query<AttachmentQuery>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authorize(&req, &pool, Scope::Read).await?;
+    let (expense_id, attachment_id) = path.into_inner();
+    require_visible(&pool, expense_id, user_id).await?;
+    let attachment = ExpenseAttachment::find(&pool, expense_id, attachment_id)
+        .await?
+        .ok_or_else(|| AppError::not_found("Attachment not found"))?;
+
+    let (key, content_type) = match query.size.as_deref() {
+        Some("thumb") => (
+            attachment
+                .thumbnail_key
+                .as_deref()
+                .ok_or_else(|| AppError::not_found("This attachment has no thumbnail"))?,
+            PROCESSED_CONTENT_TYPE,
+        ),
+        _ => (attachment.blob_key.as_str(), attachment.content_type.as_str()),
+    };
+    let data = blob_store
+        .get(key)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to read attachment {}: {}", key, e);
+            AppError::internal_error("Failed to read attachment")
+        })?
+        .ok_or_else(|| AppError::not_found("Attachment not found"))?;
+
+    // Header values must be ASCII; the JSON listing has the exact name
+    let header_name: String = attachment
+        .file_name
+        .chars()
+        .map(|c| if c.is_ascii() { c } else { '_' })
+        .collect();
+    // PDFs are stored as uploaded, so they are downloaded rather than opened
+    // on our origin, and nothing is sniffed or allowed to run scripts
+    let disposition = if content_type == PDF_CONTENT_TYPE { "attachment" } else { "inline" };
+    // An attachment's content never changes, so responses can be cached
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header((header::CONTENT_DISPOSITION, format!("{}; filename=\"{}\"", disposition, header_name)))
+        .insert_header((header::X_CONTENT_TYPE_OPTIONS, "nosniff"))
+        .insert_header((header::CONTENT_SECURITY_POLICY, "sandbox"))
+        .insert_header((header::CACHE_CONTROL, "private, max-age=31536000, immutable"))
+        .body(data))
+}
+
+/// Removes an attachment. Only its uploader or the expense's creator may.
+pub async fn delete_attachment(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    blob_store: web::Data<dyn BlobStore>,
+    path: web::Path<(Uuid, Uuid)>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authorize(&req, &pool, Scope::ExpensesWrite).await?;
+    let (expense_id, attachment_id) = path.into_inner();
+    require_visible(&pool, expense_id, user_id).await?;
+    let attachment = ExpenseAttachment::find(&pool, expense_id, attachment_id)
+        .await?
+        .ok_or_else(|| AppError::not_found("Attachment not found"))?;
+    let expense_creator: Uuid = sqlx::query_scalar(r#"SELECT created_by FROM expenses WHERE id = $1"#)
+        .bind(expense_id)
+        .fetch_one(pool.get_ref())
+        .await?;
+    if attachment.uploaded_by != user_id && expense_creator != user_id {
+        return Err(AppError::forbidden("Only the uploader or the expense's creator can remove an attachment"));
+    }
+
+    ExpenseAttachment::delete(&pool, attachment.id).await?;
+    ExpenseComment::add_system(pool.get_ref(), expense_id, user_id, &format!("removed {}", attachment.file_name))
+        .await?;
+    delete_attachment_blobs(blob_store.get_ref(), attachment.blob_keys()).await;
+    Ok(HttpResponse::NoContent().finish())
+}
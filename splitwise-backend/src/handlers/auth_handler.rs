@@ -1,14 +1,26 @@
-use actix_web::{web, HttpResponse, Responder};
+use actix_web::{http::header, web, HttpRequest, HttpResponse, Responder};
 use serde::Deserialize;
 use sqlx::PgPool;
+use crate::config::Config;
+use crate::models::email_verification::EmailVerificationToken;
+use crate::models::login_attempt::{LoginAttempt, LoginCheck, LoginOutcome};
+use crate::models::session::Session;
+use crate::models::two_factor::TwoFactor;
 use crate::models::user::{User, NewUser};
-use crate::utils::auth::{hash_password, verify_password, create_jwt};
+use crate::utils::auth::{
+    authenticate, authenticate_context, hash_password, create_jwt, create_mfa_challenge, verify_mfa_challenge,
+};
+use crate::utils::error::AppError;
+use crate::utils::helpers::{client_ip, validate_email};
+use crate::utils::jwt_keys::keys;
+use crate::utils::mailer::{EmailMessage, Mailer};
 
 #[derive(Debug, Deserialize)]
 pub struct AuthRequest {
     pub email: String,
     pub password: String,
     pub name: Option<String>, // Only for registration
+    pub device_name: Option<String>, // Shown in the session list
 }
 
 #[derive(Debug, serde::Serialize)]
@@ -17,8 +29,79 @@ pub struct AuthResponse {
     pub user: User,
 }
 
+/// Returned by `login` instead of an `AuthResponse` when the account has
+/// two-factor authentication enabled.
+#[derive(Debug, serde::Serialize)]
+pub struct TwoFactorChallengeResponse {
+    pub two_factor_required: bool,
+    pub challenge_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TwoFactorLoginRequest {
+    pub challenge_token: String,
+    pub code: Option<String>,
+    pub recovery_code: Option<String>,
+    pub device_name: Option<String>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct RefreshResponse {
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyEmailQuery {
+    pub token: String,
+}
+
+/// Issues a verification token for `email` and mails the confirmation link.
+/// Delivery failures are logged rather than surfaced, since the user can
+/// always request a new link.
+pub async fn send_verification_email(
+    pool: &PgPool,
+    mailer: &dyn Mailer,
+    config: &Config,
+    user_id: uuid::Uuid,
+    email: &str,
+) -> Result<(), sqlx::Error> {
+    let token = EmailVerificationToken::issue(pool, user_id, email).await?;
+    let link = format!("{}/api/auth/verify-email?token={}", config.public_url, token);
+    let message = EmailMessage {
+        to: email.to_string(),
+        subject: "Verify your email address".to_string(),
+        body: format!(
+            "Confirm your email address by opening the link below. It expires in 24 hours.\n\n{}",
+            link
+        ),
+    };
+    if let Err(e) = mailer.send(message).await {
+        log::error!("Failed to send verification email to user {}: {}", user_id, e);
+    }
+    Ok(())
+}
+
+/// Records a new session for the client making `req` and issues a token bound to it.
+pub async fn start_session(
+    req: &HttpRequest,
+    pool: &PgPool,
+    config: &Config,
+    user: &User,
+    device_name: Option<&str>,
+) -> Result<String, AppError> {
+    let user_agent = req.headers().get(header::USER_AGENT).and_then(|v| v.to_str().ok());
+    let ip = client_ip(req, config.trust_proxy_headers);
+    let device_name = device_name.map(str::trim).filter(|name| !name.is_empty());
+    let session = Session::create(pool, user.id, device_name, user_agent, ip.as_deref()).await?;
+    create_jwt(user.id, user.token_version, Some(session.id))
+        .map_err(|_| AppError::internal_error("Failed to create token"))
+}
+
 pub async fn register(
+    http_req: HttpRequest,
     pool: web::Data<PgPool>,
+    mailer: web::Data<dyn Mailer>,
+    config: web::Data<Config>,
     req: web::Json<AuthRequest>,
 ) -> impl Responder {
     let name = match &req.name {
@@ -26,13 +109,18 @@ pub async fn register(
         None => return HttpResponse::BadRequest().json("Name is required for registration"),
     };
 
+    let email = req.email.trim().to_string();
+    if !validate_email(&email) {
+        return HttpResponse::BadRequest().json("Invalid email address");
+    }
+
     let hashed_password = match hash_password(&req.password) {
         Ok(h) => h,
         Err(_) => return HttpResponse::InternalServerError().json("Failed to hash password"),
     };
 
     let new_user = NewUser {
-        email: req.email.clone(),
+        email,
         password_hash: hashed_password,
         name,
     };
@@ -42,7 +130,11 @@ pub async fn register(
         Err(_) => return HttpResponse::Conflict().json("User with this email already exists"),
     };
 
-    let token = match create_jwt(user.id) {
+    if send_verification_email(&pool, mailer.get_ref(), &config, user.id, &user.email).await.is_err() {
+        return HttpResponse::InternalServerError().json("Failed to create verification token");
+    }
+
+    let token = match start_session(&http_req, &pool, &config, &user, req.device_name.as_deref()).await {
         Ok(t) => t,
         Err(_) => return HttpResponse::InternalServerError().json("Failed to create token"),
     };
@@ -50,31 +142,230 @@ pub async fn register(
     HttpResponse::Ok().json(AuthResponse { token, user })
 }
 
+fn too_many_attempts(retry_after: i64) -> HttpResponse {
+    HttpResponse::TooManyRequests()
+        .insert_header((header::RETRY_AFTER, retry_after.to_string()))
+        .json("Too many failed login attempts. Try again later.")
+}
+
+async fn record_attempt(
+    pool: &PgPool,
+    email: &str,
+    ip_address: Option<&str>,
+    user_id: Option<uuid::Uuid>,
+    outcome: LoginOutcome,
+) {
+    if let Err(e) = LoginAttempt::record(pool, email, ip_address, user_id, outcome).await {
+        log::error!("Failed to record login attempt for {}: {}", email, e);
+    }
+}
+
+async fn finish_attempt(pool: &PgPool, attempt_id: uuid::Uuid, user_id: Option<uuid::Uuid>, outcome: LoginOutcome) {
+    if let Err(e) = LoginAttempt::finish(pool, attempt_id, user_id, outcome).await {
+        log::error!("Failed to record login attempt {}: {}", attempt_id, e);
+    }
+}
+
 pub async fn login(
+    http_req: HttpRequest,
     pool: web::Data<PgPool>,
+    config: web::Data<Config>,
     req: web::Json<AuthRequest>,
 ) -> impl Responder {
+    let ip = client_ip(&http_req, config.trust_proxy_headers);
+    let ip = ip.as_deref();
+
+    // Checked before the password so a locked account doesn't reveal
+    // whether a guess would have been right. The attempt counts as a
+    // failure until it turns out otherwise.
+    let attempt_id = match LoginAttempt::begin(&pool, &req.email, ip).await {
+        Ok(LoginCheck::Allowed(attempt_id)) => attempt_id,
+        Ok(LoginCheck::Locked(retry_after)) => {
+            record_attempt(&pool, &req.email, ip, None, LoginOutcome::Blocked).await;
+            return too_many_attempts(retry_after);
+        }
+        Err(_) => return HttpResponse::InternalServerError().json("Database error"),
+    };
+
     let user = match User::find_by_email(&pool, &req.email).await {
         Ok(Some(user)) => user,
         Ok(None) => return HttpResponse::Unauthorized().json("Invalid credentials"),
         Err(_) => return HttpResponse::InternalServerError().json("Database error"),
     };
 
-    match verify_password(&req.password, &user.password_hash) {
-        Ok(true) => (),
-        _ => return HttpResponse::Unauthorized().json("Invalid credentials"),
+    if !user.check_password(&req.password) {
+        finish_attempt(&pool, attempt_id, Some(user.id), LoginOutcome::Failure).await;
+        return HttpResponse::Unauthorized().json("Invalid credentials");
+    }
+
+    // The attempt only counts as a success once the second factor is
+    // verified too, so the password step can't be used to reset the counter.
+    match TwoFactor::is_enabled_for(&pool, user.id).await {
+        Ok(true) => {
+            if let Err(e) = LoginAttempt::discard(&pool, attempt_id).await {
+                log::error!("Failed to record login attempt {}: {}", attempt_id, e);
+            }
+            return match create_mfa_challenge(user.id) {
+                Ok(challenge_token) => HttpResponse::Ok().json(TwoFactorChallengeResponse {
+                    two_factor_required: true,
+                    challenge_token,
+                }),
+                Err(_) => HttpResponse::InternalServerError().json("Failed to create token"),
+            };
+        }
+        Ok(false) => (),
+        Err(_) => return HttpResponse::InternalServerError().json("Database error"),
+    }
+
+    let token = match start_session(&http_req, &pool, &config, &user, req.device_name.as_deref()).await {
+        Ok(t) => t,
+        Err(_) => return HttpResponse::InternalServerError().json("Failed to create token"),
     };
 
-    let token = match create_jwt(user.id) {
+    finish_attempt(&pool, attempt_id, Some(user.id), LoginOutcome::Success).await;
+    HttpResponse::Ok().json(AuthResponse { token, user })
+}
+
+/// Second step of a login for accounts with two-factor authentication:
+/// exchanges the challenge token plus a TOTP or recovery code for a JWT.
+pub async fn login_two_factor(
+    http_req: HttpRequest,
+    pool: web::Data<PgPool>,
+    config: web::Data<Config>,
+    req: web::Json<TwoFactorLoginRequest>,
+) -> impl Responder {
+    let claims = match verify_mfa_challenge(&req.challenge_token) {
+        Ok(claims) => claims,
+        Err(_) => return HttpResponse::Unauthorized().json("Invalid or expired challenge token"),
+    };
+    let user_id = match uuid::Uuid::parse_str(&claims.sub) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::Unauthorized().json("Invalid or expired challenge token"),
+    };
+
+    let user = match User::find_by_id(&pool, user_id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return HttpResponse::Unauthorized().json("Invalid or expired challenge token"),
+        Err(_) => return HttpResponse::InternalServerError().json("Database error"),
+    };
+
+    // Codes share the account's attempt counter with passwords, otherwise a
+    // six-digit code could be brute-forced within the challenge's lifetime.
+    let ip = client_ip(&http_req, config.trust_proxy_headers);
+    let ip = ip.as_deref();
+    let attempt_id = match LoginAttempt::begin(&pool, &user.email, ip).await {
+        Ok(LoginCheck::Allowed(attempt_id)) => attempt_id,
+        Ok(LoginCheck::Locked(retry_after)) => {
+            record_attempt(&pool, &user.email, ip, Some(user.id), LoginOutcome::Blocked).await;
+            return too_many_attempts(retry_after);
+        }
+        Err(_) => return HttpResponse::InternalServerError().json("Database error"),
+    };
+
+    let two_factor = match TwoFactor::find(&pool, user_id).await {
+        Ok(Some(tf)) if tf.is_enabled() => tf,
+        Ok(_) => return HttpResponse::Unauthorized().json("Invalid or expired challenge token"),
+        Err(_) => return HttpResponse::InternalServerError().json("Database error"),
+    };
+    match two_factor
+        .verify_second_factor(&pool, req.code.as_deref(), req.recovery_code.as_deref())
+        .await
+    {
+        Ok(true) => (),
+        Ok(false) => {
+            finish_attempt(&pool, attempt_id, Some(user.id), LoginOutcome::Failure).await;
+            return HttpResponse::Unauthorized().json("Invalid verification code");
+        }
+        Err(_) => return HttpResponse::InternalServerError().json("Database error"),
+    }
+
+    let token = match start_session(&http_req, &pool, &config, &user, req.device_name.as_deref()).await {
         Ok(t) => t,
         Err(_) => return HttpResponse::InternalServerError().json("Failed to create token"),
     };
 
+    finish_attempt(&pool, attempt_id, Some(user.id), LoginOutcome::Success).await;
     HttpResponse::Ok().json(AuthResponse { token, user })
 }
 
-pub async fn logout() -> impl Responder {
-    // On the client-side, the token should be discarded.
-    // Server-side blocklisting can be implemented here if needed.
-    HttpResponse::Ok().json("Logout successful")
-} 
\ No newline at end of file
+/// Exchanges a still-valid token for a fresh one bound to the same session,
+/// so a device stays signed in without sending the password again.
+pub async fn refresh(
+    http_req: HttpRequest,
+    pool: web::Data<PgPool>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse, AppError> {
+    let auth = authenticate_context(&http_req, &pool).await?;
+    let user = User::find_by_id(&pool, auth.user_id)
+        .await?
+        .ok_or_else(|| AppError::unauthorized("Token has been revoked"))?;
+
+    // Tokens issued before sessions were tracked get a session on first refresh
+    let token = match auth.session_id {
+        Some(session_id) => create_jwt(user.id, user.token_version, Some(session_id))
+            .map_err(|_| AppError::internal_error("Failed to create token"))?,
+        None => start_session(&http_req, &pool, &config, &user, None).await?,
+    };
+    Ok(HttpResponse::Ok().json(RefreshResponse { token }))
+}
+
+/// Revokes the session the token belongs to, so the token stops working
+/// even before it expires.
+pub async fn logout(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    let auth = authenticate_context(&req, &pool).await?;
+    if let Some(session_id) = auth.session_id {
+        Session::revoke(&pool, auth.user_id, session_id).await?;
+    }
+    Ok(HttpResponse::Ok().json("Logout successful"))
+}
+
+pub async fn verify_email(
+    pool: web::Data<PgPool>,
+    query: web::Query<VerifyEmailQuery>,
+) -> Result<HttpResponse, AppError> {
+    let token = EmailVerificationToken::consume(&pool, &query.token)
+        .await?
+        .ok_or_else(|| AppError::bad_request("Invalid or expired verification token"))?;
+
+    // Issuing a token invalidates the user's older ones, so this is the most
+    // recently requested address: either the current one or a pending change.
+    match User::confirm_email(&pool, token.user_id, &token.email).await {
+        Ok(true) => (),
+        Ok(false) => return Err(AppError::bad_request("Invalid or expired verification token")),
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            return Err(AppError::conflict("Email is already in use"));
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    Ok(HttpResponse::Ok().json("Email verified"))
+}
+
+pub async fn resend_verification(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    mailer: web::Data<dyn Mailer>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authenticate(&req, &pool).await?;
+    let user = User::find_by_id(&pool, user_id)
+        .await?
+        .ok_or_else(|| AppError::not_found("User not found"))?;
+
+    if user.is_email_verified() {
+        return Err(AppError::bad_request("Email is already verified"));
+    }
+
+    send_verification_email(&pool, mailer.get_ref(), &config, user.id, &user.email).await?;
+    Ok(HttpResponse::Ok().json("Verification email sent"))
+}
+
+/// Public half of the token signing keys, for services that verify our JWTs.
+pub async fn jwks() -> impl Responder {
+    HttpResponse::Ok()
+        .insert_header((header::CACHE_CONTROL, "public, max-age=300"))
+        .json(keys().jwks())
+}
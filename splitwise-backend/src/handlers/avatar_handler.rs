@@ -0,0 +1,204 @@
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::models::api_token::Scope;
+use crate::models::group::Group;
+use crate::models::user::User;
+use crate::utils::auth::{authenticate, authorize};
+use crate::utils::blob_store::BlobStore;
+use crate::utils::error::AppError;
+use crate::utils::images::{
+    avatar_blob_key, avatar_url, new_avatar_key, process_image, AvatarSize, ALLOWED_IMAGE_TYPES,
+    MAX_IMAGE_BYTES, PROCESSED_CONTENT_TYPE,
+};
+use crate::utils::multipart::read_files;
+
+/// Request bodies of avatar uploads may be this large, leaving room for the
+/// multipart framing around the image itself.
+pub const MAX_UPLOAD_BODY_BYTES: usize = MAX_IMAGE_BYTES + 64 * 1024;
+
+#[derive(Debug, Deserialize)]
+pub struct AvatarQuery {
+    pub size: Option<String>, // "thumb" for the 128x128 thumbnail
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct AvatarResponse {
+    pub avatar_url: Option<String>,
+}
+
+/// Pulls the `image` field out of a multipart/form-data body.
+async fn read_image_field(req: &HttpRequest, body: web::Bytes) -> Result<Vec<u8>, AppError> {
+    let image = read_files(req, body, "image", 1, MAX_IMAGE_BYTES)
+        .await?
+        .remove(0);
+    if !image.content_type.is_some_and(|t| ALLOWED_IMAGE_TYPES.contains(&t.as_str())) {
+        return Err(AppError::bad_request("Image must be a PNG, JPEG or WebP file"));
+    }
+    Ok(image.data)
+}
+
+/// Processes and stores an uploaded picture, returning its new key prefix.
+async fn store_avatar(
+    blob_store: &dyn BlobStore,
+    kind: &str,
+    owner_id: Uuid,
+    data: Vec<u8>,
+) -> Result<String, AppError> {
+    let processed = web::block(move || process_image(&data))
+        .await
+        .map_err(|_| AppError::internal_error("Failed to process image"))?
+        .map_err(|e| AppError::bad_request(&e))?;
+
+    let key = new_avatar_key(kind, owner_id);
+    for (size, data) in [(AvatarSize::Full, processed.full), (AvatarSize::Thumbnail, processed.thumbnail)] {
+        blob_store
+            .put(&avatar_blob_key(&key, size), PROCESSED_CONTENT_TYPE, data)
+            .await
+            .map_err(|e| {
+                log::error!("Failed to store avatar {}: {}", key, e);
+                AppError::internal_error("Failed to store image")
+            })?;
+    }
+    Ok(key)
+}
+
+/// Best-effort cleanup of a replaced or removed picture. A failure only
+/// leaves an orphaned file behind, so it is logged rather than surfaced.
+pub async fn delete_avatar_blobs(blob_store: &dyn BlobStore, avatar_key: Option<String>) {
+    let Some(key) = avatar_key else { return };
+    for size in [AvatarSize::Full, AvatarSize::Thumbnail] {
+        if let Err(e) = blob_store.delete(&avatar_blob_key(&key, size)).await {
+            log::warn!("Failed to delete avatar blob {}: {}", key, e);
+        }
+    }
+}
+
+async fn serve_avatar(
+    blob_store: &dyn BlobStore,
+    avatar_key: Option<String>,
+    query: &AvatarQuery,
+) -> Result<HttpResponse, AppError> {
+    let key = avatar_key.ok_or_else(|| AppError::not_found("No avatar set"))?;
+    let size = match query.size.as_deref() {
+        Some("thumb") => AvatarSize::Thumbnail,
+        _ => AvatarSize::Full,
+    };
+    let data = blob_store
+        .get(&avatar_blob_key(&key, size))
+        .await
+        .map_err(|e| {
+            log::error!("Failed to read avatar {}: {}", key, e);
+            AppError::internal_error("Failed to read image")
+        })?
+        .ok_or_else(|| AppError::not_found("No avatar set"))?;
+
+    // URLs carry the avatar version, so responses never change and can be cached
+    Ok(HttpResponse::Ok()
+        .content_type(PROCESSED_CONTENT_TYPE)
+        .insert_header((header::CACHE_CONTROL, "private, max-age=31536000, immutable"))
+        .body(data))
+}
+
+pub async fn upload_user_avatar(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    blob_store: web::Data<dyn BlobStore>,
+    body: web::Bytes,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authenticate(&req, &pool).await?;
+    let data = read_image_field(&req, body).await?;
+    let key = store_avatar(blob_store.get_ref(), "users", user_id, data).await?;
+
+    let previous = User::set_avatar_key(&pool, user_id, Some(&key)).await?;
+    delete_avatar_blobs(blob_store.get_ref(), previous).await;
+    Ok(HttpResponse::Ok().json(AvatarResponse {
+        avatar_url: avatar_url("users", user_id, Some(&key)),
+    }))
+}
+
+pub async fn delete_user_avatar(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    blob_store: web::Data<dyn BlobStore>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authenticate(&req, &pool).await?;
+    let previous = User::set_avatar_key(&pool, user_id, None).await?;
+    delete_avatar_blobs(blob_store.get_ref(), previous).await;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn get_user_avatar(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    blob_store: web::Data<dyn BlobStore>,
+    path: web::Path<Uuid>,
+    query: web::Query<AvatarQuery>,
+) -> Result<HttpResponse, AppError> {
+    let _ = authorize(&req, &pool, Scope::Read).await?;
+    let user = User::find_by_id(&pool, path.into_inner())
+        .await?
+        .ok_or_else(|| AppError::not_found("User not found"))?;
+    serve_avatar(blob_store.get_ref(), user.avatar_key, &query).await
+}
+
+async fn require_group_member(pool: &PgPool, group_id: Uuid, user_id: Uuid) -> Result<(), AppError> {
+    if !Group::is_member(pool, group_id, user_id).await? {
+        return Err(AppError::not_found("Group not found"));
+    }
+    Ok(())
+}
+
+pub async fn upload_group_avatar(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    blob_store: web::Data<dyn BlobStore>,
+    path: web::Path<Uuid>,
+    body: web::Bytes,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authorize(&req, &pool, Scope::GroupsWrite).await?;
+    let group_id = path.into_inner();
+    require_group_member(&pool, group_id, user_id).await?;
+
+    let data = read_image_field(&req, body).await?;
+    let key = store_avatar(blob_store.get_ref(), "groups", group_id, data).await?;
+    let previous = Group::set_avatar_key(&pool, group_id, Some(&key)).await?;
+    delete_avatar_blobs(blob_store.get_ref(), previous).await;
+    Ok(HttpResponse::Ok().json(AvatarResponse {
+        avatar_url: avatar_url("groups", group_id, Some(&key)),
+    }))
+}
+
+pub async fn delete_group_avatar(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    blob_store: web::Data<dyn BlobStore>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authorize(&req, &pool, Scope::GroupsWrite).await?;
+    let group_id = path.into_inner();
+    require_group_member(&pool, group_id, user_id).await?;
+
+    let previous = Group::set_avatar_key(&pool, group_id, None).await?;
+    delete_avatar_blobs(blob_store.get_ref(), previous).await;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn get_group_avatar(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    blob_store: web::Data<dyn BlobStore>,
+    path: web::Path<Uuid>,
+    query: web::Query<AvatarQuery>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authorize(&req, &pool, Scope::Read).await?;
+    let group_id = path.into_inner();
+    require_group_member(&pool, group_id, user_id).await?;
+
+    let avatar_key: Option<String> = sqlx::query_scalar(r#"SELECT avatar_key FROM groups WHERE id = $1"#)
+        .bind(group_id)
+        .fetch_one(pool.get_ref())
+        .await?;
+    serve_avatar(blob_store.get_ref(), avatar_key, &query).await
+}
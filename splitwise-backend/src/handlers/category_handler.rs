@@ -0,0 +1,174 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::models::api_token::Scope;
+use crate::models::category::{Category, CategoryRule, MAX_CATEGORY_NAME_LEN, MAX_KEYWORD_LEN};
+use crate::models::group::Group;
+use crate::utils::auth::authorize;
+use crate::utils::error::AppError;
+
+#[derive(Debug, Deserialize)]
+pub struct CategoryQuery {
+    pub group_id: Option<Uuid>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateCategoryRequest {
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateCategoryRuleRequest {
+    pub keyword: String,
+    pub category_id: Uuid,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SuggestCategoryQuery {
+    pub description: String,
+    pub group_id: Option<Uuid>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CategorySuggestion {
+    pub category: Option<Category>,
+}
+
+async fn require_member(pool: &PgPool, group_id: Uuid, user_id: Uuid) -> Result<(), AppError> {
+    if !Group::is_member(pool, group_id, user_id).await? {
+        return Err(AppError::not_found("Group not found"));
+    }
+    Ok(())
+}
+
+/// Predefined categories, plus the group's custom ones when `group_id` is given.
+pub async fn list_categories(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    query: web::Query<CategoryQuery>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authorize(&req, &pool, Scope::Read).await?;
+    if let Some(group_id) = query.group_id {
+        require_member(&pool, group_id, user_id).await?;
+    }
+    let categories = Category::list_available(&pool, query.group_id).await?;
+    Ok(HttpResponse::Ok().json(categories))
+}
+
+pub async fn create_group_category(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    body: web::Json<CreateCategoryRequest>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authorize(&req, &pool, Scope::GroupsWrite).await?;
+    let group_id = path.into_inner();
+    require_member(&pool, group_id, user_id).await?;
+
+    let name = body.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_CATEGORY_NAME_LEN {
+        return Err(AppError::bad_request(&format!(
+            "Category name must be 1 to {} characters",
+            MAX_CATEGORY_NAME_LEN
+        )));
+    }
+    let clashes_with_predefined = Category::list_available(&pool, None)
+        .await?
+        .iter()
+        .any(|category| category.name.eq_ignore_ascii_case(name));
+    if clashes_with_predefined {
+        return Err(AppError::conflict("A category with this name already exists"));
+    }
+    match Category::create(&pool, group_id, name, user_id).await {
+        Ok(category) => Ok(HttpResponse::Ok().json(category)),
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            Err(AppError::conflict("A category with this name already exists"))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub async fn delete_group_category(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<(Uuid, Uuid)>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authorize(&req, &pool, Scope::GroupsWrite).await?;
+    let (group_id, category_id) = path.into_inner();
+    require_member(&pool, group_id, user_id).await?;
+    if !Category::delete(&pool, group_id, category_id).await? {
+        return Err(AppError::not_found("Category not found"));
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// The keyword rules used to categorize the group's expenses, its own
+/// first and then the built-in ones.
+pub async fn list_category_rules(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authorize(&req, &pool, Scope::Read).await?;
+    let group_id = path.into_inner();
+    require_member(&pool, group_id, user_id).await?;
+    let rules = CategoryRule::list_for_group(&pool, Some(group_id)).await?;
+    Ok(HttpResponse::Ok().json(rules))
+}
+
+pub async fn create_category_rule(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    body: web::Json<CreateCategoryRuleRequest>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authorize(&req, &pool, Scope::GroupsWrite).await?;
+    let group_id = path.into_inner();
+    require_member(&pool, group_id, user_id).await?;
+
+    let keyword = CategoryRule::normalize_keyword(&body.keyword);
+    if keyword.is_empty() || keyword.chars().count() > MAX_KEYWORD_LEN {
+        return Err(AppError::bad_request(&format!(
+            "Keyword must be 1 to {} characters of letters or digits",
+            MAX_KEYWORD_LEN
+        )));
+    }
+    if !Category::is_available(&pool, body.category_id, Some(group_id)).await? {
+        return Err(AppError::bad_request("Unknown category for this group"));
+    }
+    match CategoryRule::create(&pool, group_id, &keyword, body.category_id).await {
+        Ok(rule) => Ok(HttpResponse::Ok().json(rule)),
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            Err(AppError::conflict("The group already has a rule for this keyword"))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub async fn delete_category_rule(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<(Uuid, Uuid)>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authorize(&req, &pool, Scope::GroupsWrite).await?;
+    let (group_id, rule_id) = path.into_inner();
+    require_member(&pool, group_id, user_id).await?;
+    if !CategoryRule::delete(&pool, group_id, rule_id).await? {
+        return Err(AppError::not_found("Category rule not found"));
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn suggest_category(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    query: web::Query<SuggestCategoryQuery>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authorize(&req, &pool, Scope::Read).await?;
+    if let Some(group_id) = query.group_id {
+        require_member(&pool, group_id, user_id).await?;
+    }
+    let category = CategoryRule::suggest(&pool, query.group_id, &query.description).await?;
+    Ok(HttpResponse::Ok().json(CategorySuggestion { category }))
+}
@@ -0,0 +1,128 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::models::api_token::Scope;
+use crate::models::comment::{ExpenseComment, MAX_COMMENT_LEN};
+use crate::models::expense::Expense;
+use crate::utils::auth::authorize;
+use crate::utils::error::AppError;
+use crate::utils::mailer::{notify_user, Mailer, NotificationKind};
+
+#[derive(Debug, Deserialize)]
+pub struct CommentRequest {
+    pub body: String,
+}
+
+async fn require_visible(pool: &PgPool, expense_id: Uuid, user_id: Uuid) -> Result<(), AppError> {
+    if !Expense::is_visible_to(pool, expense_id, user_id).await? {
+        return Err(AppError::not_found("Expense not found"));
+    }
+    Ok(())
+}
+
+fn validate_body(body: &str) -> Result<&str, AppError> {
+    let body = body.trim();
+    if body.is_empty() || body.chars().count() > MAX_COMMENT_LEN {
+        return Err(AppError::bad_request(&format!(
+            "Comment must be 1 to {} characters",
+            MAX_COMMENT_LEN
+        )));
+    }
+    Ok(body)
+}
+
+/// Finds a comment the caller may change: their own, and not a system one.
+async fn find_own(pool: &PgPool, expense_id: Uuid, comment_id: Uuid, user_id: Uuid) -> Result<ExpenseComment, AppError> {
+    let comment = ExpenseComment::find(pool, expense_id, comment_id)
+        .await?
+        .ok_or_else(|| AppError::not_found("Comment not found"))?;
+    if comment.is_system {
+        return Err(AppError::forbidden("System comments can't be changed"));
+    }
+    if comment.user_id != user_id {
+        return Err(AppError::forbidden("You can only change your own comments"));
+    }
+    Ok(comment)
+}
+
+/// The expense's thread, oldest first, with system comments recording
+/// changes to it interleaved.
+pub async fn list_comments(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authorize(&req, &pool, Scope::Read).await?;
+    let expense_id = path.into_inner();
+    require_visible(&pool, expense_id, user_id).await?;
+    let comments = ExpenseComment::list(&pool, expense_id).await?;
+    Ok(HttpResponse::Ok().json(comments))
+}
+
+pub async fn create_comment(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    mailer: web::Data<dyn Mailer>,
+    path: web::Path<Uuid>,
+    body: web::Json<CommentRequest>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authorize(&req, &pool, Scope::ExpensesWrite).await?;
+    let expense_id = path.into_inner();
+    require_visible(&pool, expense_id, user_id).await?;
+    if Expense::is_deleted(&pool, expense_id).await? {
+        return Err(AppError::bad_request("This expense was deleted"));
+    }
+    let comment = ExpenseComment::create(&pool, expense_id, user_id, validate_body(&body.body)?).await?;
+
+    let description: String = sqlx::query_scalar(r#"SELECT description FROM expenses WHERE id = $1"#)
+        .bind(expense_id)
+        .fetch_one(pool.get_ref())
+        .await?;
+    for participant in Expense::participants(&pool, expense_id).await? {
+        if participant == user_id {
+            continue;
+        }
+        notify_user(
+            pool.get_ref(),
+            mailer.get_ref(),
+            participant,
+            NotificationKind::ExpenseActivity,
+            &format!("New comment on \"{}\"", description),
+            &format!("{} commented on \"{}\":\n\n{}", comment.author_name, description, comment.body),
+        )
+        .await?;
+    }
+    Ok(HttpResponse::Ok().json(comment))
+}
+
+pub async fn update_comment(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<(Uuid, Uuid)>,
+    body: web::Json<CommentRequest>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authorize(&req, &pool, Scope::ExpensesWrite).await?;
+    let (expense_id, comment_id) = path.into_inner();
+    require_visible(&pool, expense_id, user_id).await?;
+    find_own(&pool, expense_id, comment_id, user_id).await?;
+    let comment = ExpenseComment::update(&pool, expense_id, comment_id, user_id, validate_body(&body.body)?)
+        .await?
+        .ok_or_else(|| AppError::not_found("Comment not found"))?;
+    Ok(HttpResponse::Ok().json(comment))
+}
+
+pub async fn delete_comment(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<(Uuid, Uuid)>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authorize(&req, &pool, Scope::ExpensesWrite).await?;
+    let (expense_id, comment_id) = path.into_inner();
+    require_visible(&pool, expense_id, user_id).await?;
+    find_own(&pool, expense_id, comment_id, user_id).await?;
+    if !ExpenseComment::delete(&pool, expense_id, comment_id, user_id).await? {
+        return Err(AppError::not_found("Comment not found"));
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -1,24 +1,37 @@
-use actix_web::{web, HttpRequest, HttpResponse};
-use serde::Deserialize;
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
 use sqlx::{PgPool, Row};
 use uuid::Uuid;
-use crate::utils::auth::{verify_jwt, extract_token_from_header};
+use crate::utils::auth::authorize;
+use crate::utils::blob_store::BlobStore;
 use crate::utils::error::AppError;
-use chrono::Utc;
-use crate::models::expense::Expense;
+use crate::utils::etag::{etag, precondition_failed, IfMatch};
+use chrono::{NaiveDate, Utc};
+use crate::handlers::attachment_handler::delete_attachment_blobs;
+use crate::models::api_token::Scope;
+use crate::models::attachment::ExpenseAttachment;
+use crate::models::category::{Category, CategoryRule};
+use crate::models::comment::ExpenseComment;
+use crate::models::expense::{Expense, ExpenseCursor, ExpenseFilter, ExpenseSort, EXPENSE_COLUMNS};
+use crate::models::group::Group;
+use crate::models::friendship::Friendship;
+use crate::models::revision::{ExpenseRevision, ExpenseSnapshot};
 use crate::models::split::Split;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CreateExpenseRequest {
-    pub group_id: Uuid,
+    pub group_id: Option<Uuid>, // Omit for a direct expense between friends
     pub description: String,
+    pub notes: Option<String>,
     pub amount: f64,
     pub paid_by: Uuid,
     pub split_type: String,
+    pub category_id: Option<Uuid>, // Suggested from the description when omitted
+    pub expense_date: Option<NaiveDate>, // Defaults to today in the group's timezone
     pub splits: Vec<SplitRequest>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SplitRequest {
     pub user_id: Uuid,
     pub amount: f64,
@@ -27,43 +40,95 @@ pub struct SplitRequest {
 #[derive(Debug, Deserialize)]
 pub struct UpdateExpenseRequest {
     pub description: Option<String>,
+    pub notes: Option<String>, // An empty string clears them
     pub amount: Option<f64>,
+    pub category_id: Option<Uuid>,
+    pub expense_date: Option<NaiveDate>,
     pub splits: Option<Vec<SplitRequest>>,
 }
 
-fn extract_user_id_from_request(req: &HttpRequest) -> Result<Uuid, AppError> {
-    let auth_header = req.headers().get("Authorization").and_then(|v| v.to_str().ok());
-    let token = auth_header.and_then(|h| extract_token_from_header(h));
-    let claims = token
-        .ok_or_else(|| AppError::unauthorized("Missing token"))
-        .and_then(|t| verify_jwt(&t).map_err(AppError::from))?;
-    Uuid::parse_str(&claims.sub).map_err(|_| AppError::unauthorized("Invalid user id in token"))
-}
-
 pub async fn create_expense(
     req: HttpRequest,
     pool: web::Data<PgPool>,
     body: web::Json<CreateExpenseRequest>,
 ) -> Result<HttpResponse, AppError> {
-    let user_id = extract_user_id_from_request(&req)?;
-    let row = sqlx::query(
-        r#"INSERT INTO expenses (group_id, description, amount, paid_by, split_type, created_by) VALUES ($1, $2, $3, $4, $5, $6) RETURNING id, group_id, description, amount, paid_by, split_type, created_by, created_at, updated_at"#,
-    )
-    .bind(&body.group_id)
+    let user_id = authorize(&req, &pool, Scope::ExpensesWrite).await?;
+    let expense = insert_expense(&pool, user_id, &body, None)
+        .await?
+        .ok_or_else(|| AppError::internal_error("Expense was not created"))?;
+    Ok(HttpResponse::Ok().json(expense))
+}
+
+/// Checks a new expense on behalf of `user_id` and resolves its category,
+/// suggesting one from the description when none was given.
+pub async fn validate_expense(
+    pool: &PgPool,
+    user_id: Uuid,
+    body: &CreateExpenseRequest,
+) -> Result<Option<Uuid>, AppError> {
+    check_participants(pool, body.group_id, user_id, body.paid_by, &body.splits).await?;
+    match body.category_id {
+        Some(category_id) => {
+            if !Category::is_available(pool, category_id, body.group_id).await? {
+                return Err(AppError::bad_request("Unknown category for this group"));
+            }
+            Ok(Some(category_id))
+        }
+        None => Ok(CategoryRule::suggest(pool, body.group_id, &body.description)
+            .await?
+            .map(|category| category.id)),
+    }
+}
+
+/// Validates and stores an expense with its splits in one transaction. Both
+/// the API and the recurring expense scheduler create expenses through here;
+/// the scheduler passes the `occurrence` being materialized, which also dates
+/// the expense, and gets `None` back if that occurrence already exists.
+pub async fn insert_expense(
+    pool: &PgPool,
+    user_id: Uuid,
+    body: &CreateExpenseRequest,
+    occurrence: Option<(Uuid, NaiveDate)>,
+) -> Result<Option<Expense>, AppError> {
+    let category_id = validate_expense(pool, user_id, body).await?;
+    let (recurring_expense_id, occurrence_date) = occurrence.unzip();
+    let expense_date = match occurrence_date.or(body.expense_date) {
+        Some(date) => date,
+        None => Expense::today(pool, body.group_id, user_id).await?,
+    };
+    let mut tx = pool.begin().await?;
+    let row = sqlx::query(&format!(
+        r#"INSERT INTO expenses (group_id, description, amount, paid_by, split_type, category_id, recurring_expense_id, occurrence_date, expense_date, created_by, notes) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, NULLIF($11, '')) ON CONFLICT (recurring_expense_id, occurrence_date) DO NOTHING RETURNING {}"#,
+        EXPENSE_COLUMNS
+    ))
+    .bind(body.group_id)
     .bind(&body.description)
-    .bind(&body.amount)
-    .bind(&body.paid_by)
+    .bind(body.amount)
+    .bind(body.paid_by)
     .bind(&body.split_type)
-    .bind(&user_id)
-    .fetch_one(pool.get_ref())
+    .bind(category_id)
+    .bind(recurring_expense_id)
+    .bind(occurrence_date)
+    .bind(expense_date)
+    .bind(user_id)
+    .bind(&body.notes)
+    .fetch_optional(&mut *tx)
     .await?;
+    let Some(row) = row else {
+        return Ok(None);
+    };
     let expense = Expense {
         id: row.try_get("id")?,
         group_id: row.try_get("group_id")?,
         description: row.try_get("description")?,
+        notes: row.try_get("notes")?,
         amount: row.try_get("amount")?,
         paid_by: row.try_get("paid_by")?,
         split_type: row.try_get("split_type")?,
+        category_id: row.try_get("category_id")?,
+        recurring_expense_id: row.try_get("recurring_expense_id")?,
+        expense_date: row.try_get("expense_date")?,
+        version: row.try_get("version")?,
         created_by: row.try_get("created_by")?,
         created_at: row.try_get("created_at")?,
         updated_at: row.try_get("updated_at")?,
@@ -73,49 +138,174 @@ pub async fn create_expense(
         let _ = sqlx::query(
             r#"INSERT INTO splits (expense_id, user_id, amount) VALUES ($1, $2, $3)"#,
         )
-        .bind(&expense.id)
-        .bind(&split.user_id)
-        .bind(&split.amount)
-        .execute(pool.get_ref())
+        .bind(expense.id)
+        .bind(split.user_id)
+        .bind(split.amount)
+        .execute(&mut *tx)
         .await?;
     }
-    Ok(HttpResponse::Ok().json(expense))
+    let snapshot = ExpenseSnapshot::load(&mut tx, expense.id).await?;
+    ExpenseRevision::record(&mut tx, expense.id, user_id, "created", &snapshot.diff(None)).await?;
+    let action = match occurrence {
+        Some(_) => "added this expense from a recurring schedule",
+        None => "added this expense",
+    };
+    ExpenseComment::add_system(&mut *tx, expense.id, user_id, action).await?;
+    tx.commit().await?;
+    Ok(Some(expense))
+}
+
+/// Checks who `user_id` may put on an expense, inside a group or outside one.
+async fn check_participants(
+    pool: &PgPool,
+    group_id: Option<Uuid>,
+    user_id: Uuid,
+    paid_by: Uuid,
+    splits: &[SplitRequest],
+) -> Result<(), AppError> {
+    match group_id {
+        Some(group_id) => check_group_participants(pool, group_id, user_id, paid_by, splits).await,
+        None => check_direct_participants(pool, user_id, paid_by, splits).await,
+    }
 }
 
+/// Only members can add to a group, and only between its members.
+async fn check_group_participants(
+    pool: &PgPool,
+    group_id: Uuid,
+    user_id: Uuid,
+    paid_by: Uuid,
+    splits: &[SplitRequest],
+) -> Result<(), AppError> {
+    if !Group::is_member(pool, group_id, user_id).await? {
+        return Err(AppError::not_found("Group not found"));
+    }
+    let mut participants: Vec<Uuid> = splits.iter().map(|split| split.user_id).collect();
+    participants.push(paid_by);
+    participants.sort();
+    participants.dedup();
+    for participant in participants.into_iter().filter(|id| *id != user_id) {
+        if !Group::is_member(pool, group_id, participant).await? {
+            return Err(AppError::bad_request("Everyone on a group expense must be a member of the group"));
+        }
+    }
+    Ok(())
+}
+
+/// A direct expense has no group to vouch for its participants, so the
+/// creator must take part and everyone else involved must be their friend.
+async fn check_direct_participants(
+    pool: &PgPool,
+    user_id: Uuid,
+    paid_by: Uuid,
+    splits: &[SplitRequest],
+) -> Result<(), AppError> {
+    let mut participants: Vec<Uuid> = splits.iter().map(|split| split.user_id).collect();
+    participants.push(paid_by);
+    participants.sort();
+    participants.dedup();
+    if !participants.contains(&user_id) {
+        return Err(AppError::bad_request("You must be part of an expense outside a group"));
+    }
+    if participants.len() < 2 {
+        return Err(AppError::bad_request("An expense outside a group needs at least one friend"));
+    }
+    for participant in participants.into_iter().filter(|id| *id != user_id) {
+        if !Friendship::are_friends(pool, user_id, participant).await? {
+            return Err(AppError::bad_request(
+                "Everyone on an expense outside a group must be your friend",
+            ));
+        }
+    }
+    Ok(())
+}
+
+const EXPENSES_DEFAULT_LIMIT: i64 = 50;
+const EXPENSES_MAX_LIMIT: i64 = 200;
+
+#[derive(Debug, Deserialize)]
+pub struct ExpenseQuery {
+    pub group_id: Option<Uuid>,
+    pub friend_id: Option<Uuid>,
+    pub paid_by: Option<Uuid>,
+    pub participant_id: Option<Uuid>,
+    pub category_id: Option<Uuid>,
+    pub date_from: Option<NaiveDate>,
+    pub date_to: Option<NaiveDate>,
+    pub min_amount: Option<f64>,
+    pub max_amount: Option<f64>,
+    pub q: Option<String>,
+    #[serde(default)]
+    pub sort: ExpenseSort,
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExpensePage {
+    pub expenses: Vec<Expense>,
+    pub next_cursor: Option<String>, // None on the last page
+}
+
+/// Lists expenses the caller takes part in, or a group's or a friend's
+/// when asked for, newest first unless sorted otherwise. Pages continue
+/// from the `next_cursor` of the previous one, which only works with the
+/// same sort.
 pub async fn get_expenses(
     req: HttpRequest,
     pool: web::Data<PgPool>,
-    query: web::Query<std::collections::HashMap<String, String>>,
 ) -> Result<HttpResponse, AppError> {
-    let user_id = extract_user_id_from_request(&req)?;
-    let group_id = query.get("group_id").and_then(|s| Uuid::parse_str(s).ok());
-    let rows = if let Some(gid) = group_id {
-        sqlx::query(
-            r#"SELECT * FROM expenses WHERE group_id = $1"#,
-        )
-        .bind(&gid)
-        .fetch_all(pool.get_ref())
-        .await?
+    let user_id = authorize(&req, &pool, Scope::Read).await?;
+    let query = web::Query::<ExpenseQuery>::from_query(req.query_string())
+        .map_err(|e| AppError::bad_request(&format!("Invalid query: {}", e)))?
+        .into_inner();
+
+    if let Some(group_id) = query.group_id {
+        if !Group::is_member(&pool, group_id, user_id).await? {
+            return Err(AppError::not_found("Group not found"));
+        }
+    }
+    if let (Some(date_from), Some(date_to)) = (query.date_from, query.date_to) {
+        if date_from > date_to {
+            return Err(AppError::bad_request("date_from can't be after date_to"));
+        }
+    }
+    if let (Some(min_amount), Some(max_amount)) = (query.min_amount, query.max_amount) {
+        if min_amount > max_amount {
+            return Err(AppError::bad_request("min_amount can't be more than max_amount"));
+        }
+    }
+    let cursor = match query.cursor.as_deref() {
+        Some(cursor) => Some(
+            ExpenseCursor::decode(cursor)
+                .filter(|cursor| cursor.sort == query.sort)
+                .ok_or_else(|| AppError::bad_request("Invalid cursor for this sort"))?,
+        ),
+        None => None,
+    };
+    let limit = query.limit.unwrap_or(EXPENSES_DEFAULT_LIMIT).clamp(1, EXPENSES_MAX_LIMIT);
+
+    let filter = ExpenseFilter {
+        group_id: query.group_id,
+        friend_id: query.friend_id,
+        paid_by: query.paid_by,
+        participant_id: query.participant_id,
+        category_id: query.category_id,
+        date_from: query.date_from,
+        date_to: query.date_to,
+        min_amount: query.min_amount,
+        max_amount: query.max_amount,
+        text: query.q.map(|q| q.trim().to_string()).filter(|q| !q.is_empty()),
+    };
+    // One extra row tells us whether there is another page
+    let mut expenses = Expense::list(&pool, user_id, &filter, query.sort, cursor.as_ref(), limit + 1).await?;
+    let next_cursor = if expenses.len() as i64 > limit {
+        expenses.truncate(limit as usize);
+        expenses.last().map(|last| ExpenseCursor::after(last, query.sort).encode())
     } else {
-        sqlx::query(
-            r#"SELECT * FROM expenses WHERE created_by = $1"#,
-        )
-        .bind(&user_id)
-        .fetch_all(pool.get_ref())
-        .await?
+        None
     };
-    let expenses: Vec<Expense> = rows.into_iter().map(|row| Expense {
-        id: row.try_get("id").unwrap(),
-        group_id: row.try_get("group_id").unwrap(),
-        description: row.try_get("description").unwrap(),
-        amount: row.try_get("amount").unwrap(),
-        paid_by: row.try_get("paid_by").unwrap(),
-        split_type: row.try_get("split_type").unwrap(),
-        created_by: row.try_get("created_by").unwrap(),
-        created_at: row.try_get("created_at").unwrap(),
-        updated_at: row.try_get("updated_at").unwrap(),
-    }).collect();
-    Ok(HttpResponse::Ok().json(expenses))
+    Ok(HttpResponse::Ok().json(ExpensePage { expenses, next_cursor }))
 }
 
 pub async fn get_expense_by_id(
@@ -123,26 +313,15 @@ pub async fn get_expense_by_id(
     pool: web::Data<PgPool>,
     path: web::Path<Uuid>,
 ) -> Result<HttpResponse, AppError> {
-    let _ = extract_user_id_from_request(&req)?;
+    let user_id = authorize(&req, &pool, Scope::Read).await?;
     let expense_id = path.into_inner();
-    let row = sqlx::query(
-        r#"SELECT * FROM expenses WHERE id = $1"#,
-    )
-    .bind(&expense_id)
-    .fetch_one(pool.get_ref())
-    .await?;
-    let expense = Expense {
-        id: row.try_get("id")?,
-        group_id: row.try_get("group_id")?,
-        description: row.try_get("description")?,
-        amount: row.try_get("amount")?,
-        paid_by: row.try_get("paid_by")?,
-        split_type: row.try_get("split_type")?,
-        created_by: row.try_get("created_by")?,
-        created_at: row.try_get("created_at")?,
-        updated_at: row.try_get("updated_at")?,
-    };
-    Ok(HttpResponse::Ok().json(expense))
+    require_visible(&pool, expense_id, user_id).await?;
+    let expense = Expense::find(&pool, expense_id)
+        .await?
+        .ok_or_else(|| AppError::not_found("Expense not found"))?;
+    Ok(HttpResponse::Ok()
+        .insert_header((header::ETAG, etag(expense.version)))
+        .json(expense))
 }
 
 pub async fn update_expense(
@@ -151,70 +330,215 @@ pub async fn update_expense(
     path: web::Path<Uuid>,
     body: web::Json<UpdateExpenseRequest>,
 ) -> Result<HttpResponse, AppError> {
-    let _ = extract_user_id_from_request(&req)?;
+    let user_id = authorize(&req, &pool, Scope::ExpensesWrite).await?;
     let expense_id = path.into_inner();
-    let row = sqlx::query(
-        r#"UPDATE expenses SET description = COALESCE($1, description), amount = COALESCE($2, amount), updated_at = $3 WHERE id = $4 RETURNING *"#,
+    // Locked for the rest of the transaction, so the version check holds and
+    // the recorded revision diffs against what this update actually replaced
+    let mut tx = pool.begin().await?;
+    let before: (Option<Uuid>, Uuid, String, f64, NaiveDate, Option<String>, i32) = sqlx::query_as(
+        r#"SELECT e.group_id, e.paid_by, e.description, e.amount::FLOAT8, e.expense_date, c.name, e.version FROM expenses e LEFT JOIN categories c ON c.id = e.category_id WHERE e.id = $1 AND e.deleted_at IS NULL FOR UPDATE OF e"#,
     )
+    .bind(expense_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| AppError::not_found("Expense not found"))?;
+    let (group_id, paid_by, old_description, old_amount, old_date, old_category, version) = before;
+    require_visible(&pool, expense_id, user_id).await?;
+    if !IfMatch::from_request(&req)?.matches(version) {
+        drop(tx);
+        return stale_expense(&pool, expense_id).await;
+    }
+    if let Some(category_id) = body.category_id {
+        if !Category::is_available(&pool, category_id, group_id).await? {
+            return Err(AppError::bad_request("Unknown category for this group"));
+        }
+    }
+    if let Some(splits) = &body.splits {
+        check_participants(&pool, group_id, user_id, paid_by, splits).await?;
+    }
+    let snapshot_before = ExpenseSnapshot::load(&mut tx, expense_id).await?;
+    let row = sqlx::query(&format!(
+        r#"UPDATE expenses SET description = COALESCE($1, description), amount = COALESCE($2, amount), category_id = COALESCE($3, category_id), expense_date = COALESCE($6, expense_date), notes = NULLIF(COALESCE($7, notes), ''), version = version + 1, updated_at = $4 WHERE id = $5 RETURNING {}"#,
+        EXPENSE_COLUMNS
+    ))
     .bind(&body.description)
-    .bind(&body.amount)
-    .bind(&Utc::now())
-    .bind(&expense_id)
-    .fetch_one(pool.get_ref())
+    .bind(body.amount)
+    .bind(body.category_id)
+    .bind(Utc::now())
+    .bind(expense_id)
+    .bind(body.expense_date)
+    .bind(&body.notes)
+    .fetch_one(&mut *tx)
     .await?;
+    if let Some(splits) = &body.splits {
+        sqlx::query(r#"DELETE FROM splits WHERE expense_id = $1"#)
+            .bind(expense_id)
+            .execute(&mut *tx)
+            .await?;
+        for split in splits {
+            sqlx::query(r#"INSERT INTO splits (expense_id, user_id, amount) VALUES ($1, $2, $3)"#)
+                .bind(expense_id)
+                .bind(split.user_id)
+                .bind(split.amount)
+                .execute(&mut *tx)
+                .await?;
+        }
+    }
+
+    let mut changes = Vec::new();
+    if let Some(description) = body.description.as_deref().filter(|d| *d != old_description) {
+        changes.push(format!("changed the description from \"{}\" to \"{}\"", old_description, description));
+    }
+    if let Some(amount) = body.amount.filter(|a| (a - old_amount).abs() >= 0.005) {
+        changes.push(format!(
+            "changed the amount from {} to {}",
+            format_plain_amount(old_amount),
+            format_plain_amount(amount)
+        ));
+    }
+    if let Some(date) = body.expense_date.filter(|d| *d != old_date) {
+        changes.push(format!("changed the date from {} to {}", old_date, date));
+    }
+    if let Some(category_id) = body.category_id {
+        let new_category: String = sqlx::query_scalar(r#"SELECT name FROM categories WHERE id = $1"#)
+            .bind(category_id)
+            .fetch_one(&mut *tx)
+            .await?;
+        if old_category.as_deref() != Some(new_category.as_str()) {
+            changes.push(match old_category {
+                Some(old_category) => format!("changed the category from {} to {}", old_category, new_category),
+                None => format!("set the category to {}", new_category),
+            });
+        }
+    }
+    let field_changes = ExpenseSnapshot::load(&mut tx, expense_id)
+        .await?
+        .diff(Some(&snapshot_before));
+    if field_changes.iter().any(|change| change.field == "splits") {
+        changes.push("changed how the expense is split".to_string());
+    }
+    for change in &changes {
+        ExpenseComment::add_system(&mut *tx, expense_id, user_id, change).await?;
+    }
+    if !field_changes.is_empty() {
+        ExpenseRevision::record(&mut tx, expense_id, user_id, "updated", &field_changes).await?;
+    }
+    tx.commit().await?;
+
     let expense = Expense {
         id: row.try_get("id")?,
         group_id: row.try_get("group_id")?,
         description: row.try_get("description")?,
+        notes: row.try_get("notes")?,
         amount: row.try_get("amount")?,
         paid_by: row.try_get("paid_by")?,
         split_type: row.try_get("split_type")?,
+        category_id: row.try_get("category_id")?,
+        recurring_expense_id: row.try_get("recurring_expense_id")?,
+        expense_date: row.try_get("expense_date")?,
+        version: row.try_get("version")?,
         created_by: row.try_get("created_by")?,
         created_at: row.try_get("created_at")?,
         updated_at: row.try_get("updated_at")?,
     };
-    Ok(HttpResponse::Ok().json(expense))
+    Ok(HttpResponse::Ok()
+        .insert_header((header::ETAG, etag(expense.version)))
+        .json(expense))
+}
+
+/// Hides expenses the caller can't see behind a 404, like unknown ones.
+async fn require_visible(pool: &PgPool, expense_id: Uuid, user_id: Uuid) -> Result<(), AppError> {
+    if !Expense::is_visible_to(pool, expense_id, user_id).await? {
+        return Err(AppError::not_found("Expense not found"));
+    }
+    Ok(())
+}
+
+/// Answers an update or delete made against an outdated version with the
+/// expense as it is now. Only reached after `require_visible`.
+async fn stale_expense(pool: &PgPool, expense_id: Uuid) -> Result<HttpResponse, AppError> {
+    let expense = Expense::find(pool, expense_id)
+        .await?
+        .ok_or_else(|| AppError::not_found("Expense not found"))?;
+    Ok(precondition_failed(expense.version, &expense))
 }
 
 pub async fn delete_expense(
     req: HttpRequest,
     pool: web::Data<PgPool>,
+    blob_store: web::Data<dyn BlobStore>,
     path: web::Path<Uuid>,
 ) -> Result<HttpResponse, AppError> {
-    let _ = extract_user_id_from_request(&req)?;
+    let user_id = authorize(&req, &pool, Scope::ExpensesWrite).await?;
     let expense_id = path.into_inner();
-    let _ = sqlx::query(
-        r#"DELETE FROM expenses WHERE id = $1"#,
+    // Soft delete, so the comment thread survives the expense. Receipts
+    // aren't kept around though.
+    let mut tx = pool.begin().await?;
+    let version: i32 = sqlx::query_scalar(
+        r#"SELECT version FROM expenses WHERE id = $1 AND deleted_at IS NULL FOR UPDATE"#,
     )
-    .bind(&expense_id)
-    .execute(pool.get_ref())
+    .bind(expense_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| AppError::not_found("Expense not found"))?;
+    require_visible(&pool, expense_id, user_id).await?;
+    if !IfMatch::from_request(&req)?.matches(version) {
+        drop(tx);
+        return stale_expense(&pool, expense_id).await;
+    }
+    sqlx::query(
+        r#"UPDATE expenses SET deleted_at = NOW(), deleted_by = $2, version = version + 1 WHERE id = $1"#,
+    )
+    .bind(expense_id)
+    .bind(user_id)
+    .execute(&mut *tx)
     .await?;
+    ExpenseComment::add_system(&mut *tx, expense_id, user_id, "deleted this expense").await?;
+    ExpenseRevision::record(&mut tx, expense_id, user_id, "deleted", &[]).await?;
+    let attachment_keys = ExpenseAttachment::delete_for_expense(&mut *tx, expense_id).await?;
+    tx.commit().await?;
+    delete_attachment_blobs(blob_store.get_ref(), attachment_keys).await;
     Ok(HttpResponse::Ok().json("Expense deleted"))
 }
 
+/// Amounts in system comments: "40" rather than "40.00", but "40.50".
+fn format_plain_amount(amount: f64) -> String {
+    if amount.fract() == 0.0 {
+        format!("{:.0}", amount)
+    } else {
+        format!("{:.2}", amount)
+    }
+}
+
 pub async fn get_splits_for_expense(
     req: HttpRequest,
     pool: web::Data<PgPool>,
     path: web::Path<Uuid>,
 ) -> Result<HttpResponse, AppError> {
-    let _ = extract_user_id_from_request(&req)?;
+    let user_id = authorize(&req, &pool, Scope::Read).await?;
     let expense_id = path.into_inner();
+    require_visible(&pool, expense_id, user_id).await?;
     let rows = sqlx::query(
-        r#"SELECT * FROM splits WHERE expense_id = $1"#,
+        r#"SELECT id, expense_id, user_id, amount::FLOAT8 AS amount, is_settled, settled_at, created_at, updated_at FROM splits WHERE expense_id = $1"#,
     )
-    .bind(&expense_id)
+    .bind(expense_id)
     .fetch_all(pool.get_ref())
     .await?;
-let splits: Vec<Split> = rows.into_iter().map(|row| Split {
-    id: row.try_get("id").unwrap(),
-    expense_id: row.try_get("expense_id").unwrap(),
-    user_id: row.try_get("user_id").unwrap(),
-    amount: row.try_get("amount").unwrap(),
-    is_settled: row.try_get("is_settled").unwrap(),
-    settled_at: row.try_get("settled_at").ok(),
-    created_at: row.try_get("created_at").unwrap(),
-    updated_at: row.try_get("updated_at").unwrap(),
-    }).collect();
+    let splits = rows
+        .into_iter()
+        .map(|row| {
+            Ok(Split {
+                id: row.try_get("id")?,
+                expense_id: row.try_get("expense_id")?,
+                user_id: row.try_get("user_id")?,
+                amount: row.try_get("amount")?,
+                is_settled: row.try_get("is_settled")?,
+                settled_at: row.try_get("settled_at")?,
+                created_at: row.try_get("created_at")?,
+                updated_at: row.try_get("updated_at")?,
+            })
+        })
+        .collect::<Result<Vec<Split>, sqlx::Error>>()?;
     Ok(HttpResponse::Ok().json(splits))
 }
 
@@ -223,15 +547,31 @@ pub async fn settle_expense(
     pool: web::Data<PgPool>,
     path: web::Path<Uuid>,
 ) -> Result<HttpResponse, AppError> {
-    let user_id = extract_user_id_from_request(&req)?;
+    let user_id = authorize(&req, &pool, Scope::ExpensesWrite).await?;
     let expense_id = path.into_inner();
     let _ = sqlx::query(
         r#"UPDATE splits SET is_settled = TRUE, settled_at = $1 WHERE expense_id = $2 AND user_id = $3"#,
     )
-    .bind(&Utc::now())
-    .bind(&expense_id)
-    .bind(&user_id)
+    .bind(Utc::now())
+    .bind(expense_id)
+    .bind(user_id)
     .execute(pool.get_ref())
     .await?;
     Ok(HttpResponse::Ok().json("Expense settled"))
+}
+
+/// Every recorded change to the expense, oldest first, with field-level
+/// diffs. Stays available after the expense is deleted.
+pub async fn get_expense_history(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authorize(&req, &pool, Scope::Read).await?;
+    let expense_id = path.into_inner();
+    if !Expense::is_visible_to(&pool, expense_id, user_id).await? {
+        return Err(AppError::not_found("Expense not found"));
+    }
+    let revisions = ExpenseRevision::list(&pool, expense_id).await?;
+    Ok(HttpResponse::Ok().json(revisions))
 }
\ No newline at end of file
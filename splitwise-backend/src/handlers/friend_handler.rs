@@ -0,0 +1,112 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::models::api_token::Scope;
+use crate::models::friendship::Friendship;
+use crate::models::user::User;
+use crate::utils::auth::authorize;
+use crate::utils::error::AppError;
+
+#[derive(Debug, Deserialize)]
+pub struct FriendRequestBody {
+    pub user_id: Option<Uuid>,
+    pub email: Option<String>, // Only verified accounts can be found this way
+}
+
+pub async fn get_friends(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authorize(&req, &pool, Scope::Read).await?;
+    let friends = Friendship::friends_of(&pool, user_id).await?;
+    Ok(HttpResponse::Ok().json(friends))
+}
+
+pub async fn get_friend_requests(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authorize(&req, &pool, Scope::Read).await?;
+    let requests = Friendship::pending_for(&pool, user_id).await?;
+    Ok(HttpResponse::Ok().json(requests))
+}
+
+/// Sends a friend request. If the other person already asked us, this
+/// accepts their request instead of creating a second one.
+pub async fn send_friend_request(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    body: web::Json<FriendRequestBody>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authorize(&req, &pool, Scope::FriendsWrite).await?;
+    let friend_id = match (&body.user_id, &body.email) {
+        (Some(friend_id), _) => User::find_by_id(&pool, *friend_id)
+            .await?
+            .ok_or_else(|| AppError::not_found("User not found"))?
+            .id,
+        (None, Some(email)) => User::find_by_email(&pool, email.trim())
+            .await?
+            .filter(|user| user.is_email_verified())
+            .ok_or_else(|| AppError::not_found("No user with this email"))?
+            .id,
+        (None, None) => return Err(AppError::bad_request("Either user_id or email is required")),
+    };
+    if friend_id == user_id {
+        return Err(AppError::bad_request("You can't add yourself as a friend"));
+    }
+
+    let friendship = match Friendship::find_between(&pool, user_id, friend_id).await? {
+        Some(existing) if existing.is_accepted() => return Err(AppError::conflict("You are already friends")),
+        Some(existing) if existing.addressee_id == user_id => Friendship::accept(&pool, existing.id, user_id)
+            .await?
+            .ok_or_else(|| AppError::conflict("Friend request changed, try again"))?,
+        Some(_) => return Err(AppError::conflict("Friend request already sent")),
+        None => match Friendship::request(&pool, user_id, friend_id).await {
+            Ok(friendship) => friendship,
+            Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+                return Err(AppError::conflict("Friend request already sent"));
+            }
+            Err(e) => return Err(e.into()),
+        },
+    };
+    Ok(HttpResponse::Ok().json(friendship))
+}
+
+pub async fn accept_friend_request(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authorize(&req, &pool, Scope::FriendsWrite).await?;
+    let friendship = Friendship::accept(&pool, path.into_inner(), user_id)
+        .await?
+        .ok_or_else(|| AppError::not_found("Friend request not found"))?;
+    Ok(HttpResponse::Ok().json(friendship))
+}
+
+pub async fn delete_friend_request(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authorize(&req, &pool, Scope::FriendsWrite).await?;
+    if !Friendship::delete_request(&pool, path.into_inner(), user_id).await? {
+        return Err(AppError::not_found("Friend request not found"));
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Unfriends someone. Direct expenses with them are kept, so balances and
+/// history are unaffected.
+pub async fn remove_friend(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authorize(&req, &pool, Scope::FriendsWrite).await?;
+    if !Friendship::remove(&pool, user_id, path.into_inner()).await? {
+        return Err(AppError::not_found("Friend not found"));
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -1,36 +1,48 @@
-use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
 use serde::Deserialize;
 use sqlx::{PgPool, Row};
 use uuid::Uuid;
-use crate::models::group::Group;
-use crate::utils::auth::{verify_jwt, extract_token_from_header};
+use crate::handlers::attachment_handler::delete_attachment_blobs;
+use crate::handlers::avatar_handler::delete_avatar_blobs;
+use crate::models::api_token::Scope;
+use crate::models::attachment::ExpenseAttachment;
+use crate::models::group::{Group, GroupResponse};
+use crate::models::preferences::UserPreferences;
+use crate::models::user::User;
+use crate::utils::auth::authorize;
+use crate::utils::blob_store::BlobStore;
 use crate::utils::error::AppError;
+use crate::utils::etag::{etag, precondition_failed, IfMatch};
+use crate::utils::mailer::{notify_user, Mailer, NotificationKind};
 use chrono::Utc;
+use chrono_tz::Tz;
 
 #[derive(Debug, Deserialize)]
 pub struct CreateGroupRequest {
     pub name: String,
     pub description: Option<String>,
+    pub timezone: Option<String>, // Defaults to the creator's timezone
 }
 
 #[derive(Debug, Deserialize)]
 pub struct UpdateGroupRequest {
     pub name: Option<String>,
     pub description: Option<String>,
+    pub timezone: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct AddMemberRequest {
-    pub user_id: Uuid,
+    pub user_id: Option<Uuid>,
+    pub email: Option<String>, // Invite by email; only verified accounts can be found this way
 }
 
-fn extract_user_id_from_request(req: &HttpRequest) -> Result<Uuid, AppError> {
-    let auth_header = req.headers().get("Authorization").and_then(|v| v.to_str().ok());
-    let token = auth_header.and_then(|h| extract_token_from_header(h));
-    let claims = token
-        .ok_or_else(|| AppError::unauthorized("Missing token"))
-        .and_then(|t| verify_jwt(&t).map_err(AppError::from))?;
-    Uuid::parse_str(&claims.sub).map_err(|_| AppError::unauthorized("Invalid user id in token"))
+fn validate_timezone(timezone: &str) -> Result<String, AppError> {
+    let timezone = timezone.trim();
+    if timezone.parse::<Tz>().is_err() {
+        return Err(AppError::bad_request("Timezone must be an IANA name such as Europe/Berlin"));
+    }
+    Ok(timezone.to_string())
 }
 
 pub async fn create_group(
@@ -38,70 +50,122 @@ pub async fn create_group(
     pool: web::Data<PgPool>,
     body: web::Json<CreateGroupRequest>,
 ) -> Result<HttpResponse, AppError> {
-    let user_id = extract_user_id_from_request(&req)?;
+    let user_id = authorize(&req, &pool, Scope::GroupsWrite).await?;
+    let timezone = match &body.timezone {
+        Some(timezone) => validate_timezone(timezone)?,
+        None => UserPreferences::find(&pool, user_id).await?.timezone,
+    };
+    // The creator joins as an admin, and can be removed like any member
+    let mut tx = pool.begin().await?;
     let row = sqlx::query(
-        r#"INSERT INTO groups (name, description, created_by) VALUES ($1, $2, $3) RETURNING id, name, description, created_by, created_at, updated_at"#,
+        r#"INSERT INTO groups (name, description, timezone, created_by) VALUES ($1, $2, $3, $4) RETURNING id, name, description, created_by, avatar_key, timezone, version, created_at, updated_at"#,
     )
     .bind(&body.name)
     .bind(&body.description)
-    .bind(&user_id)
-    .fetch_one(pool.get_ref())
+    .bind(&timezone)
+    .bind(user_id)
+    .fetch_one(&mut *tx)
     .await?;
+    let group_id: Uuid = row.try_get("id")?;
+    sqlx::query(r#"INSERT INTO group_members (group_id, user_id, role) VALUES ($1, $2, 'admin')"#)
+        .bind(group_id)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
     let group = Group {
         id: row.try_get("id")?,
         name: row.try_get("name")?,
         description: row.try_get("description")?,
         created_by: row.try_get("created_by")?,
+        avatar_key: row.try_get("avatar_key")?,
+        timezone: row.try_get("timezone")?,
+        version: row.try_get("version")?,
         created_at: row.try_get("created_at")?,
         updated_at: row.try_get("updated_at")?,
     };
-    Ok(HttpResponse::Ok().json(group))
+    Ok(HttpResponse::Ok().json(group.to_response(1)))
 }
 
 pub async fn get_groups(
     req: HttpRequest,
     pool: web::Data<PgPool>,
 ) -> Result<HttpResponse, AppError> {
-    let user_id = extract_user_id_from_request(&req)?;
+    let user_id = authorize(&req, &pool, Scope::Read).await?;
     let rows = sqlx::query(
-        r#"SELECT * FROM groups WHERE created_by = $1"#,
+        r#"SELECT g.*, (SELECT COUNT(*) FROM group_members m WHERE m.group_id = g.id) AS member_count FROM groups g WHERE EXISTS (SELECT 1 FROM group_members m WHERE m.group_id = g.id AND m.user_id = $1)"#,
     )
-    .bind(&user_id)
+    .bind(user_id)
     .fetch_all(pool.get_ref())
     .await?;
-    let groups: Vec<Group> = rows.into_iter().map(|row| Group {
+    let groups: Vec<GroupResponse> = rows.into_iter().map(|row| Group {
         id: row.try_get("id").unwrap(),
         name: row.try_get("name").unwrap(),
         description: row.try_get("description").unwrap(),
         created_by: row.try_get("created_by").unwrap(),
+        avatar_key: row.try_get("avatar_key").unwrap(),
+        timezone: row.try_get("timezone").unwrap(),
+        version: row.try_get("version").unwrap(),
         created_at: row.try_get("created_at").unwrap(),
         updated_at: row.try_get("updated_at").unwrap(),
-    }).collect();
+    }.to_response(row.try_get("member_count").unwrap())).collect();
     Ok(HttpResponse::Ok().json(groups))
 }
 
-pub async fn get_group_by_id(
-    req: HttpRequest,
-    pool: web::Data<PgPool>,
-    path: web::Path<Uuid>,
-) -> Result<HttpResponse, AppError> {
-    let _ = extract_user_id_from_request(&req)?;
-    let group_id = path.into_inner();
+async fn find_group(pool: &PgPool, group_id: Uuid) -> Result<Option<GroupResponse>, AppError> {
     let row = sqlx::query(
-        r#"SELECT * FROM groups WHERE id = $1"#,
+        r#"SELECT g.*, (SELECT COUNT(*) FROM group_members m WHERE m.group_id = g.id) AS member_count FROM groups g WHERE id = $1"#,
     )
-    .bind(&group_id)
-    .fetch_one(pool.get_ref())
+    .bind(group_id)
+    .fetch_optional(pool)
     .await?;
+    let Some(row) = row else {
+        return Ok(None);
+    };
     let group = Group {
         id: row.try_get("id")?,
         name: row.try_get("name")?,
         description: row.try_get("description")?,
         created_by: row.try_get("created_by")?,
+        avatar_key: row.try_get("avatar_key")?,
+        timezone: row.try_get("timezone")?,
+        version: row.try_get("version")?,
         created_at: row.try_get("created_at")?,
         updated_at: row.try_get("updated_at")?,
     };
-    Ok(HttpResponse::Ok().json(group))
+    Ok(Some(group.to_response(row.try_get("member_count")?)))
+}
+
+async fn require_member(pool: &PgPool, group_id: Uuid, user_id: Uuid) -> Result<(), AppError> {
+    if !Group::is_member(pool, group_id, user_id).await? {
+        return Err(AppError::not_found("Group not found"));
+    }
+    Ok(())
+}
+
+/// Answers an update or delete made against an outdated version with the
+/// group as it is now. Only reached after `require_member`.
+async fn stale_group(pool: &PgPool, group_id: Uuid) -> Result<HttpResponse, AppError> {
+    let group = find_group(pool, group_id)
+        .await?
+        .ok_or_else(|| AppError::not_found("Group not found"))?;
+    Ok(precondition_failed(group.version, &group))
+}
+
+pub async fn get_group_by_id(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authorize(&req, &pool, Scope::Read).await?;
+    let group_id = path.into_inner();
+    require_member(&pool, group_id, user_id).await?;
+    let group = find_group(&pool, group_id)
+        .await?
+        .ok_or_else(|| AppError::not_found("Group not found"))?;
+    Ok(HttpResponse::Ok()
+        .insert_header((header::ETAG, etag(group.version)))
+        .json(group))
 }
 
 pub async fn update_group(
@@ -110,59 +174,122 @@ pub async fn update_group(
     path: web::Path<Uuid>,
     body: web::Json<UpdateGroupRequest>,
 ) -> Result<HttpResponse, AppError> {
-    let _ = extract_user_id_from_request(&req)?;
+    let user_id = authorize(&req, &pool, Scope::GroupsWrite).await?;
     let group_id = path.into_inner();
+    require_member(&pool, group_id, user_id).await?;
+    let if_match = IfMatch::from_request(&req)?;
+    let timezone = body.timezone.as_deref().map(validate_timezone).transpose()?;
     let row = sqlx::query(
-        r#"UPDATE groups SET name = COALESCE($1, name), description = COALESCE($2, description), updated_at = $3 WHERE id = $4 RETURNING *"#,
+        r#"UPDATE groups SET name = COALESCE($1, name), description = COALESCE($2, description), timezone = COALESCE($5, timezone), version = version + 1, updated_at = $3 WHERE id = $4 AND ($6::INT[] IS NULL OR version = ANY($6)) RETURNING *, (SELECT COUNT(*) FROM group_members m WHERE m.group_id = groups.id) AS member_count"#,
     )
     .bind(&body.name)
     .bind(&body.description)
-    .bind(&Utc::now())
-    .bind(&group_id)
-    .fetch_one(pool.get_ref())
+    .bind(Utc::now())
+    .bind(group_id)
+    .bind(timezone)
+    .bind(if_match.versions())
+    .fetch_optional(pool.get_ref())
     .await?;
+    let Some(row) = row else {
+        return stale_group(&pool, group_id).await;
+    };
     let group = Group {
         id: row.try_get("id")?,
         name: row.try_get("name")?,
         description: row.try_get("description")?,
         created_by: row.try_get("created_by")?,
+        avatar_key: row.try_get("avatar_key")?,
+        timezone: row.try_get("timezone")?,
+        version: row.try_get("version")?,
         created_at: row.try_get("created_at")?,
         updated_at: row.try_get("updated_at")?,
-    };
-    Ok(HttpResponse::Ok().json(group))
+    }
+    .to_response(row.try_get("member_count")?);
+    Ok(HttpResponse::Ok()
+        .insert_header((header::ETAG, etag(group.version)))
+        .json(group))
 }
 
 pub async fn delete_group(
     req: HttpRequest,
     pool: web::Data<PgPool>,
+    blob_store: web::Data<dyn BlobStore>,
     path: web::Path<Uuid>,
 ) -> Result<HttpResponse, AppError> {
-    let _ = extract_user_id_from_request(&req)?;
+    let user_id = authorize(&req, &pool, Scope::GroupsWrite).await?;
     let group_id = path.into_inner();
-    let _ = sqlx::query(
-        r#"DELETE FROM groups WHERE id = $1"#,
+    require_member(&pool, group_id, user_id).await?;
+    let created_by: Uuid = sqlx::query_scalar(r#"SELECT created_by FROM groups WHERE id = $1"#)
+        .bind(group_id)
+        .fetch_optional(pool.get_ref())
+        .await?
+        .ok_or_else(|| AppError::not_found("Group not found"))?;
+    if created_by != user_id {
+        return Err(AppError::forbidden("Only the group's creator can delete it"));
+    }
+    let if_match = IfMatch::from_request(&req)?;
+    // The group's expenses and their attachment rows go with it, so note
+    // which blobs to remove first
+    let attachment_keys = ExpenseAttachment::blob_keys_for_group(&pool, group_id).await?;
+    let avatar_key: Option<Option<String>> = sqlx::query_scalar(
+        r#"DELETE FROM groups WHERE id = $1 AND ($2::INT[] IS NULL OR version = ANY($2)) RETURNING avatar_key"#,
     )
-    .bind(&group_id)
-    .execute(pool.get_ref())
+    .bind(group_id)
+    .bind(if_match.versions())
+    .fetch_optional(pool.get_ref())
     .await?;
+    let Some(avatar_key) = avatar_key else {
+        return stale_group(&pool, group_id).await;
+    };
+    delete_avatar_blobs(blob_store.get_ref(), avatar_key).await;
+    delete_attachment_blobs(blob_store.get_ref(), attachment_keys).await;
     Ok(HttpResponse::Ok().json("Group deleted"))
 }
 
 pub async fn add_group_member(
     req: HttpRequest,
     pool: web::Data<PgPool>,
+    mailer: web::Data<dyn Mailer>,
     path: web::Path<Uuid>,
     body: web::Json<AddMemberRequest>,
 ) -> Result<HttpResponse, AppError> {
-    let _ = extract_user_id_from_request(&req)?;
+    let _ = authorize(&req, &pool, Scope::GroupsWrite).await?;
     let group_id = path.into_inner();
+    let member_id = match (&body.user_id, &body.email) {
+        (Some(user_id), _) => *user_id,
+        (None, Some(email)) => {
+            let user = User::find_by_email(pool.get_ref(), email.trim())
+                .await?
+                .ok_or_else(|| AppError::not_found("No user with this email"))?;
+            if !user.is_email_verified() {
+                return Err(AppError::bad_request("This user has not verified their email address yet"));
+            }
+            user.id
+        }
+        (None, None) => return Err(AppError::bad_request("Either user_id or email is required")),
+    };
     sqlx::query(
         r#"INSERT INTO group_members (group_id, user_id) VALUES ($1, $2) RETURNING *"#,
     )
-    .bind(&group_id)
-    .bind(&body.user_id)
+    .bind(group_id)
+    .bind(member_id)
     .fetch_one(pool.get_ref())
     .await?;
+
+    let group_name: String = sqlx::query_scalar(r#"SELECT name FROM groups WHERE id = $1"#)
+        .bind(group_id)
+        .fetch_one(pool.get_ref())
+        .await?;
+    notify_user(
+        pool.get_ref(),
+        mailer.get_ref(),
+        member_id,
+        NotificationKind::GroupActivity,
+        "You were added to a group",
+        &format!("You have been added to the group \"{}\".", group_name),
+    )
+    .await?;
+
     Ok(HttpResponse::Ok().json("Member added"))
 }
 
@@ -171,13 +298,13 @@ pub async fn remove_group_member(
     pool: web::Data<PgPool>,
     path: web::Path<(Uuid, Uuid)>,
 ) -> Result<HttpResponse, AppError> {
-    let _ = extract_user_id_from_request(&req)?;
+    let _ = authorize(&req, &pool, Scope::GroupsWrite).await?;
     let (group_id, user_id) = path.into_inner();
     let _ = sqlx::query(
         r#"DELETE FROM group_members WHERE group_id = $1 AND user_id = $2"#,
     )
-    .bind(&group_id)
-    .bind(&user_id)
+    .bind(group_id)
+    .bind(user_id)
     .execute(pool.get_ref())
     .await?;
     Ok(HttpResponse::Ok().json("Member removed"))
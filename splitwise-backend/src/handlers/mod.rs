@@ -1,4 +1,17 @@
+pub mod api_token_handler;
+pub mod attachment_handler;
 pub mod auth_handler;
+pub mod avatar_handler;
+pub mod category_handler;
+pub mod comment_handler;
 pub mod expense_handler;
+pub mod friend_handler;
 pub mod group_handler;
+pub mod oidc_handler;
+pub mod preferences_handler;
+pub mod recurring_expense_handler;
+pub mod report_handler;
+pub mod search_handler;
+pub mod session_handler;
+pub mod two_factor_handler;
 pub mod user_handler; 
\ No newline at end of file
@@ -0,0 +1,125 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use crate::config::{Config, OidcConfig};
+use crate::handlers::auth_handler::{start_session, AuthResponse, TwoFactorChallengeResponse};
+use crate::models::identity::{OidcLoginState, UserIdentity};
+use crate::models::two_factor::TwoFactor;
+use crate::models::user::User;
+use crate::utils::auth::create_mfa_challenge;
+use crate::utils::error::AppError;
+use crate::utils::oidc::{authorization_url, fetch_user_info, UserInfo};
+
+#[derive(Debug, Serialize)]
+pub struct AuthorizeResponse {
+    pub authorization_url: String,
+    pub login_secret: String, // Kept by the client and sent back to /callback
+}
+
+/// Sent by the client once the provider redirects back to `OIDC_REDIRECT_URI`.
+#[derive(Debug, Deserialize)]
+pub struct CallbackRequest {
+    pub code: String,
+    pub state: String,
+    pub login_secret: String,
+    pub device_name: Option<String>,
+}
+
+fn oidc_config(config: &Config) -> Result<&OidcConfig, AppError> {
+    config
+        .oidc
+        .as_ref()
+        .ok_or_else(|| AppError::not_found("Single sign-on is not configured"))
+}
+
+/// Starts an authorization-code + PKCE login. The client sends the user to
+/// the returned URL and keeps `login_secret` to itself until the callback,
+/// which ties the login to this client: a victim can't be made to complete a
+/// login someone else started (and so end up signed in to their account).
+pub async fn authorize(
+    pool: web::Data<PgPool>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse, AppError> {
+    let oidc = oidc_config(&config)?;
+    let (state, code_verifier, login_secret) = OidcLoginState::create(&pool).await?;
+    let url = authorization_url(oidc, &state, &code_verifier).map_err(|e| {
+        log::error!("Failed to build OIDC authorization URL: {}", e);
+        AppError::internal_error("Single sign-on is misconfigured")
+    })?;
+    Ok(HttpResponse::Ok().json(AuthorizeResponse { authorization_url: url, login_secret }))
+}
+
+/// Finds the account for an external identity, linking or creating one on
+/// first sign-in. Linking by email needs the address verified on both sides,
+/// otherwise whoever registered it first could take over the other account.
+async fn resolve_user(pool: &PgPool, provider: &str, info: &UserInfo) -> Result<User, AppError> {
+    if let Some(identity) = UserIdentity::find(pool, provider, &info.sub).await? {
+        identity.record_login(pool, info.email.as_deref()).await?;
+        return User::find_by_id(pool, identity.user_id)
+            .await?
+            .ok_or_else(|| AppError::unauthorized("Account not found"));
+    }
+
+    let email = match &info.email {
+        Some(email) if info.email_verified => email.trim(),
+        _ => return Err(AppError::bad_request("The identity provider did not return a verified email address")),
+    };
+
+    let user = match User::find_by_email(pool, email).await? {
+        Some(user) if user.is_email_verified() => user,
+        Some(_) => {
+            return Err(AppError::conflict(
+                "An account with this email already exists. Sign in with your password and verify your email first.",
+            ))
+        }
+        None => {
+            let name = info
+                .name
+                .as_deref()
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .unwrap_or_else(|| email.split('@').next().unwrap_or(email));
+            match User::create_passwordless(pool, name, email).await {
+                Ok(user) => user,
+                Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+                    return Err(AppError::conflict("Email is already in use"));
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    };
+
+    UserIdentity::link(pool, user.id, provider, &info.sub, Some(email)).await?;
+    Ok(user)
+}
+
+pub async fn callback(
+    http_req: HttpRequest,
+    pool: web::Data<PgPool>,
+    config: web::Data<Config>,
+    body: web::Json<CallbackRequest>,
+) -> Result<HttpResponse, AppError> {
+    let oidc = oidc_config(&config)?;
+    let code_verifier = OidcLoginState::consume(&pool, &body.state, &body.login_secret)
+        .await?
+        .ok_or_else(|| AppError::bad_request("Invalid or expired login request"))?;
+
+    let info = fetch_user_info(oidc, &body.code, &code_verifier).await.map_err(|e| {
+        log::warn!("OIDC code exchange with {} failed: {}", oidc.provider, e);
+        AppError::unauthorized("Sign-in with the identity provider failed")
+    })?;
+    let user = resolve_user(&pool, &oidc.provider, &info).await?;
+
+    // Same second step as a password login
+    if TwoFactor::is_enabled_for(&pool, user.id).await? {
+        let challenge_token = create_mfa_challenge(user.id)
+            .map_err(|_| AppError::internal_error("Failed to create token"))?;
+        return Ok(HttpResponse::Ok().json(TwoFactorChallengeResponse {
+            two_factor_required: true,
+            challenge_token,
+        }));
+    }
+
+    let token = start_session(&http_req, &pool, &config, &user, body.device_name.as_deref()).await?;
+    Ok(HttpResponse::Ok().json(AuthResponse { token, user }))
+}
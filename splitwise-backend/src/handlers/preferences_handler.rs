@@ -0,0 +1,60 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use sqlx::PgPool;
+use crate::models::api_token::Scope;
+use crate::models::preferences::UserPreferences;
+use crate::utils::auth::{authenticate, authorize};
+use crate::utils::error::AppError;
+
+/// Partial update; omitted fields keep their current value.
+#[derive(Debug, Deserialize)]
+pub struct UpdatePreferencesRequest {
+    pub currency: Option<String>,
+    pub locale: Option<String>,
+    pub timezone: Option<String>,
+    pub date_format: Option<String>,
+    pub notify_group_activity: Option<bool>,
+    pub notify_expense_activity: Option<bool>,
+}
+
+pub async fn get_preferences(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authorize(&req, &pool, Scope::Read).await?;
+    let preferences = UserPreferences::find(&pool, user_id).await?;
+    Ok(HttpResponse::Ok().json(preferences))
+}
+
+pub async fn update_preferences(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    body: web::Json<UpdatePreferencesRequest>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authenticate(&req, &pool).await?;
+    let body = body.into_inner();
+
+    let mut preferences = UserPreferences::find(&pool, user_id).await?;
+    if let Some(currency) = body.currency {
+        preferences.currency = currency.trim().to_uppercase();
+    }
+    if let Some(locale) = body.locale {
+        preferences.locale = locale.trim().to_string();
+    }
+    if let Some(timezone) = body.timezone {
+        preferences.timezone = timezone.trim().to_string();
+    }
+    if let Some(date_format) = body.date_format {
+        preferences.date_format = date_format.trim().to_string();
+    }
+    if let Some(notify) = body.notify_group_activity {
+        preferences.notify_group_activity = notify;
+    }
+    if let Some(notify) = body.notify_expense_activity {
+        preferences.notify_expense_activity = notify;
+    }
+    preferences.validate().map_err(AppError::bad_request)?;
+
+    preferences.save(&pool, user_id).await?;
+    Ok(HttpResponse::Ok().json(preferences))
+}
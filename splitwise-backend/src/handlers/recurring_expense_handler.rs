@@ -0,0 +1,254 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::NaiveDate;
+use serde::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::handlers::expense_handler::{validate_expense, CreateExpenseRequest, SplitRequest};
+use crate::models::api_token::Scope;
+use crate::models::group::Group;
+use crate::models::expense::Expense;
+use crate::models::recurring_expense::{Frequency, RecurringExpense, RecurringExpenseTemplate, SplitDefinition};
+use crate::utils::auth::authorize;
+use crate::utils::error::AppError;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateRecurringExpenseRequest {
+    #[serde(flatten)]
+    pub expense: CreateExpenseRequest,
+    pub frequency: Frequency,
+    pub start_date: NaiveDate,
+    pub end_date: Option<NaiveDate>,
+}
+
+/// Changes to future occurrences. The group and schedule can't change;
+/// create a new recurring expense for that.
+#[derive(Debug, Deserialize)]
+pub struct UpdateRecurringExpenseRequest {
+    pub description: Option<String>,
+    pub amount: Option<f64>,
+    pub paid_by: Option<Uuid>,
+    pub split_type: Option<String>,
+    pub category_id: Option<Uuid>,
+    pub splits: Option<Vec<SplitRequest>>,
+    pub end_date: Option<NaiveDate>,
+    #[serde(default)]
+    pub clear_end_date: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RecurringExpenseQuery {
+    pub group_id: Option<Uuid>,
+}
+
+impl From<RecurringExpenseTemplate> for CreateExpenseRequest {
+    fn from(template: RecurringExpenseTemplate) -> Self {
+        CreateExpenseRequest {
+            group_id: template.group_id,
+            description: template.description,
+            notes: None,
+            amount: template.amount,
+            paid_by: template.paid_by,
+            split_type: template.split_type,
+            category_id: template.category_id,
+            expense_date: None, // Each occurrence is dated by its schedule
+            splits: template
+                .splits
+                .into_iter()
+                .map(|split| SplitRequest { user_id: split.user_id, amount: split.amount })
+                .collect(),
+        }
+    }
+}
+
+fn to_template(expense: &CreateExpenseRequest, category_id: Option<Uuid>) -> RecurringExpenseTemplate {
+    RecurringExpenseTemplate {
+        group_id: expense.group_id,
+        description: expense.description.clone(),
+        amount: expense.amount,
+        paid_by: expense.paid_by,
+        split_type: expense.split_type.clone(),
+        category_id,
+        splits: expense
+            .splits
+            .iter()
+            .map(|split| SplitDefinition { user_id: split.user_id, amount: split.amount })
+            .collect(),
+    }
+}
+
+/// The creator of a direct recurring expense or anyone taking part in it can
+/// manage it, as can any member of the group for a group's, though not a
+/// creator who has since left. Others get a 404 so ids don't leak.
+async fn find_managed(pool: &PgPool, id: Uuid, user_id: Uuid) -> Result<RecurringExpense, AppError> {
+    let recurring = RecurringExpense::find(pool, id)
+        .await?
+        .ok_or_else(|| AppError::not_found("Recurring expense not found"))?;
+    let allowed = match recurring.group_id {
+        Some(group_id) => Group::is_member(pool, group_id, user_id).await?,
+        None => recurring.created_by == user_id || recurring.involves(user_id),
+    };
+    if !allowed {
+        return Err(AppError::not_found("Recurring expense not found"));
+    }
+    Ok(recurring)
+}
+
+fn validate_schedule(start_date: NaiveDate, end_date: Option<NaiveDate>) -> Result<(), AppError> {
+    match end_date {
+        Some(end_date) if end_date < start_date => {
+            Err(AppError::bad_request("End date can't be before the start date"))
+        }
+        _ => Ok(()),
+    }
+}
+
+pub async fn create_recurring_expense(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    body: web::Json<CreateRecurringExpenseRequest>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authorize(&req, &pool, Scope::ExpensesWrite).await?;
+    validate_schedule(body.start_date, body.end_date)?;
+    if body.expense.expense_date.is_some() {
+        return Err(AppError::bad_request("Occurrences are dated by the schedule; use start_date instead"));
+    }
+    let category_id = validate_expense(&pool, user_id, &body.expense).await?;
+    let recurring = RecurringExpense::create(
+        &pool,
+        user_id,
+        &to_template(&body.expense, category_id),
+        body.frequency,
+        body.start_date,
+        body.end_date,
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(recurring))
+}
+
+pub async fn get_recurring_expenses(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    query: web::Query<RecurringExpenseQuery>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authorize(&req, &pool, Scope::Read).await?;
+    let recurring = RecurringExpense::list_visible(&pool, user_id, query.group_id).await?;
+    Ok(HttpResponse::Ok().json(recurring))
+}
+
+pub async fn get_recurring_expense(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authorize(&req, &pool, Scope::Read).await?;
+    let recurring = find_managed(&pool, path.into_inner(), user_id).await?;
+    Ok(HttpResponse::Ok().json(recurring))
+}
+
+/// Edits future occurrences. Expenses already created from the schedule
+/// are ordinary expenses and are edited on their own.
+pub async fn update_recurring_expense(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    body: web::Json<UpdateRecurringExpenseRequest>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authorize(&req, &pool, Scope::ExpensesWrite).await?;
+    let recurring = find_managed(&pool, path.into_inner(), user_id).await?;
+    let body = body.into_inner();
+
+    let mut expense = CreateExpenseRequest::from(recurring.template());
+    if let Some(description) = body.description {
+        expense.description = description;
+    }
+    if let Some(amount) = body.amount {
+        expense.amount = amount;
+    }
+    if let Some(paid_by) = body.paid_by {
+        expense.paid_by = paid_by;
+    }
+    if let Some(split_type) = body.split_type {
+        expense.split_type = split_type;
+    }
+    if body.category_id.is_some() {
+        expense.category_id = body.category_id;
+    }
+    if let Some(splits) = body.splits {
+        expense.splits = splits;
+    }
+    let end_date = if body.clear_end_date { None } else { body.end_date.or(recurring.end_date) };
+    validate_schedule(recurring.start_date, end_date)?;
+
+    let category_id = validate_expense(&pool, user_id, &expense).await?;
+    let updated = recurring
+        .update(&pool, &to_template(&expense, category_id), end_date)
+        .await?
+        .ok_or_else(|| AppError::conflict("The next occurrence changed, try again"))?;
+    Ok(HttpResponse::Ok().json(updated))
+}
+
+pub async fn pause_recurring_expense(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authorize(&req, &pool, Scope::ExpensesWrite).await?;
+    let recurring = find_managed(&pool, path.into_inner(), user_id).await?;
+    let recurring = recurring.set_paused(&pool, true).await?;
+    Ok(HttpResponse::Ok().json(recurring))
+}
+
+/// Resumes a paused schedule from today (in the group's timezone, or the
+/// creator's for a direct expense): occurrences that fell due while
+/// it was paused are skipped rather than back-filled.
+pub async fn resume_recurring_expense(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authorize(&req, &pool, Scope::ExpensesWrite).await?;
+    let recurring = find_managed(&pool, path.into_inner(), user_id).await?;
+    if recurring.is_paused {
+        let today = Expense::today(&pool, recurring.group_id, recurring.created_by).await?;
+        let mut sequence = recurring.sequence;
+        while recurring.occurrence(sequence).is_some_and(|date| date < today) {
+            sequence += 1;
+        }
+        if sequence != recurring.sequence {
+            recurring.advance(&pool, recurring.sequence, sequence).await?;
+        }
+    }
+    let recurring = recurring.set_paused(&pool, false).await?;
+    Ok(HttpResponse::Ok().json(recurring))
+}
+
+/// Skips the next occurrence only; the schedule carries on after it.
+pub async fn skip_recurring_occurrence(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authorize(&req, &pool, Scope::ExpensesWrite).await?;
+    let id = path.into_inner();
+    let recurring = find_managed(&pool, id, user_id).await?;
+    if recurring.next_occurrence.is_none() {
+        return Err(AppError::bad_request("This recurring expense has no upcoming occurrences"));
+    }
+    if !recurring.advance(&pool, recurring.sequence, recurring.sequence + 1).await? {
+        return Err(AppError::conflict("The next occurrence changed, try again"));
+    }
+    let recurring = find_managed(&pool, id, user_id).await?;
+    Ok(HttpResponse::Ok().json(recurring))
+}
+
+/// Stops the schedule. Expenses it already created are kept.
+pub async fn delete_recurring_expense(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authorize(&req, &pool, Scope::ExpensesWrite).await?;
+    let recurring = find_managed(&pool, path.into_inner(), user_id).await?;
+    recurring.delete(&pool).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
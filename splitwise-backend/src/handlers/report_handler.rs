@@ -0,0 +1,175 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{Datelike, Months, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use uuid::Uuid;
+use crate::models::api_token::Scope;
+use crate::models::preferences::UserPreferences;
+use crate::models::split::Split;
+use crate::utils::auth::authorize;
+use crate::utils::error::AppError;
+
+const DEFAULT_REPORT_MONTHS: u32 = 6;
+const MAX_REPORT_MONTHS: u32 = 36;
+
+#[derive(Debug, Deserialize)]
+pub struct ReportQuery {
+    pub months: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MonthlyTotals {
+    pub period_start: NaiveDate,
+    pub label: String, // period_start in the user's date format
+    pub total_paid: f64,
+    pub total_share: f64,
+    pub total_paid_formatted: String,
+    pub total_share_formatted: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SpendingReport {
+    pub currency: String,
+    pub timezone: String,
+    pub months: Vec<MonthlyTotals>,
+}
+
+/// Month-by-month totals of what the caller paid and what their share was,
+/// by the date of each expense. The current month is the one in the caller's
+/// timezone and amounts are formatted with their currency and locale.
+pub async fn get_spending_report(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    query: web::Query<ReportQuery>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authorize(&req, &pool, Scope::Read).await?;
+    let preferences = UserPreferences::find(&pool, user_id).await?;
+    let tz = preferences.tz();
+    let months = query.months.unwrap_or(DEFAULT_REPORT_MONTHS).clamp(1, MAX_REPORT_MONTHS);
+
+    let today = Utc::now().with_timezone(&tz).date_naive();
+    let first_month = NaiveDate::from_ymd_opt(today.year(), today.month(), 1)
+        .and_then(|d| d.checked_sub_months(Months::new(months - 1)))
+        .ok_or_else(|| AppError::internal_error("Invalid report range"))?;
+    let rows: Vec<(NaiveDate, f64, f64)> = sqlx::query_as(
+        r#"
+        SELECT date_trunc('month', expense_date)::DATE AS period_start,
+               SUM(paid)::FLOAT8,
+               SUM(share)::FLOAT8
+        FROM (
+            SELECT e.expense_date, e.amount AS paid, 0 AS share
+            FROM expenses e
+            WHERE e.paid_by = $1 AND e.deleted_at IS NULL
+            UNION ALL
+            SELECT e.expense_date, 0, s.amount
+            FROM splits s
+            JOIN expenses e ON e.id = s.expense_id
+            WHERE s.user_id = $1 AND e.deleted_at IS NULL
+        ) entries
+        WHERE expense_date >= $2
+        GROUP BY 1
+        "#,
+    )
+    .bind(user_id)
+    .bind(first_month)
+    .fetch_all(pool.get_ref())
+    .await?;
+    let totals: HashMap<NaiveDate, (f64, f64)> =
+        rows.into_iter().map(|(month, paid, share)| (month, (paid, share))).collect();
+
+    // Every month in the range is listed, including ones without expenses
+    let months = (0..months)
+        .filter_map(|offset| first_month.checked_add_months(Months::new(offset)))
+        .map(|period_start| {
+            let (total_paid, total_share) = totals.get(&period_start).copied().unwrap_or((0.0, 0.0));
+            MonthlyTotals {
+                period_start,
+                label: preferences.format_date(period_start),
+                total_paid,
+                total_share,
+                total_paid_formatted: preferences.format_amount(total_paid),
+                total_share_formatted: preferences.format_amount(total_share),
+            }
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(SpendingReport {
+        currency: preferences.currency.clone(),
+        timezone: preferences.timezone.clone(),
+        months,
+    }))
+}
+
+#[derive(Debug, Serialize)]
+pub struct GroupBalance {
+    pub group_id: Uuid,
+    pub name: String,
+    pub net_balance: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PersonBalance {
+    pub user_id: Uuid,
+    pub name: String,
+    pub net_balance: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BalanceSummary {
+    pub currency: String,
+    pub total_owed: f64,  // Others owe the caller
+    pub total_owing: f64, // The caller owes others
+    pub net_balance: f64,
+    pub total_owed_formatted: String,
+    pub total_owing_formatted: String,
+    pub groups: Vec<GroupBalance>,
+    pub direct_balance: f64, // Expenses between friends outside any group
+    pub people: Vec<PersonBalance>,
+}
+
+/// The home screen: what the caller is owed and owes overall, broken down
+/// by group and by person. Totals are netted per person, so someone who
+/// owes in one group and is owed in another only counts once.
+pub async fn get_balance_summary(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authorize(&req, &pool, Scope::Read).await?;
+    let preferences = UserPreferences::find(&pool, user_id).await?;
+    let entries = Split::balances_for(&pool, user_id).await?;
+
+    let mut groups = Vec::new();
+    let mut people = Vec::new();
+    let mut direct_balance = 0.0;
+    for entry in entries {
+        match (entry.is_group, entry.id) {
+            (true, Some(group_id)) => groups.push(GroupBalance {
+                group_id,
+                name: entry.name.unwrap_or_default(),
+                net_balance: entry.net_balance,
+            }),
+            (true, None) => direct_balance = entry.net_balance,
+            (false, Some(other_id)) => people.push(PersonBalance {
+                user_id: other_id,
+                name: entry.name.unwrap_or_default(),
+                net_balance: entry.net_balance,
+            }),
+            (false, None) => {}
+        }
+    }
+
+    let total_owed: f64 = people.iter().map(|p| p.net_balance).filter(|b| *b > 0.0).sum();
+    let total_owing: f64 = -people.iter().map(|p| p.net_balance).filter(|b| *b < 0.0).sum::<f64>();
+    Ok(HttpResponse::Ok().json(BalanceSummary {
+        currency: preferences.currency.clone(),
+        total_owed,
+        total_owing,
+        net_balance: total_owed - total_owing,
+        total_owed_formatted: preferences.format_amount(total_owed),
+        total_owing_formatted: preferences.format_amount(total_owing),
+        groups,
+        direct_balance,
+        people,
+    }))
+}
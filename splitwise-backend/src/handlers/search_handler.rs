@@ -0,0 +1,57 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use crate::models::api_token::Scope;
+use crate::models::search::{search, SearchHit, HIGHLIGHT_END, HIGHLIGHT_START};
+use crate::utils::auth::authorize;
+use crate::utils::error::AppError;
+use crate::utils::helpers::escape_html;
+
+const SEARCH_DEFAULT_LIMIT: i64 = 20;
+const SEARCH_MAX_LIMIT: i64 = 50;
+const SEARCH_MAX_QUERY_LEN: usize = 200;
+
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    pub q: String,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchResponse {
+    pub query: String,
+    pub results: Vec<SearchHit>,
+}
+
+/// Snippets are returned as HTML: the text is escaped and matches are
+/// wrapped in `<mark>`.
+fn highlight(snippet: &str) -> String {
+    escape_html(snippet)
+        .replace(HIGHLIGHT_START, "<mark>")
+        .replace(HIGHLIGHT_END, "</mark>")
+}
+
+/// Full-text search across the caller's expenses, comments and groups, best
+/// matches first.
+pub async fn search_all(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    query: web::Query<SearchQuery>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authorize(&req, &pool, Scope::Read).await?;
+    let q = query.q.trim();
+    if q.is_empty() {
+        return Err(AppError::bad_request("Search query can't be empty"));
+    }
+    if q.chars().count() > SEARCH_MAX_QUERY_LEN {
+        return Err(AppError::bad_request("Search query is too long"));
+    }
+    let limit = query.limit.unwrap_or(SEARCH_DEFAULT_LIMIT).clamp(1, SEARCH_MAX_LIMIT);
+
+    let results = search(&pool, user_id, q, limit)
+        .await?
+        .into_iter()
+        .map(|hit| SearchHit { snippet: highlight(&hit.snippet), ..hit })
+        .collect();
+    Ok(HttpResponse::Ok().json(SearchResponse { query: q.to_string(), results }))
+}
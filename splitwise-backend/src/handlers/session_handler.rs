@@ -0,0 +1,45 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::models::session::{Session, SessionResponse};
+use crate::models::user::User;
+use crate::utils::auth::authenticate_context;
+use crate::utils::error::AppError;
+
+pub async fn list_sessions(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    let auth = authenticate_context(&req, &pool).await?;
+    let sessions: Vec<SessionResponse> = Session::list_active(&pool, auth.user_id)
+        .await?
+        .into_iter()
+        .map(|session| session.to_response(auth.session_id))
+        .collect();
+    Ok(HttpResponse::Ok().json(sessions))
+}
+
+pub async fn revoke_session(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let auth = authenticate_context(&req, &pool).await?;
+    let session_id = path.into_inner();
+    if !Session::revoke(&pool, auth.user_id, session_id).await? {
+        return Err(AppError::not_found("Session not found"));
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Logs out everywhere, including the caller. Bumping the token version also
+/// invalidates tokens issued before sessions were tracked.
+pub async fn revoke_all_sessions(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    let auth = authenticate_context(&req, &pool).await?;
+    Session::revoke_all(&pool, auth.user_id, None).await?;
+    User::revoke_tokens(&pool, auth.user_id).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
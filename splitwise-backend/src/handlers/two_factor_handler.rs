@@ -0,0 +1,120 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::models::two_factor::{RecoveryCode, TwoFactor};
+use crate::models::user::User;
+use crate::utils::auth::{authenticate, authenticate_context, confirm_identity};
+use crate::utils::error::AppError;
+use crate::utils::totp::otpauth_uri;
+
+#[derive(Debug, Serialize)]
+pub struct EnrollResponse {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConfirmRequest {
+    pub code: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RecoveryCodesResponse {
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DisableRequest {
+    pub password: Option<String>, // Required unless the account is passwordless and just signed in
+    pub code: Option<String>,
+    pub recovery_code: Option<String>,
+}
+
+async fn find_user(pool: &PgPool, user_id: Uuid) -> Result<User, AppError> {
+    User::find_by_id(pool, user_id)
+        .await?
+        .ok_or_else(|| AppError::not_found("User not found"))
+}
+
+pub async fn enroll(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authenticate(&req, &pool).await?;
+    if TwoFactor::is_enabled_for(&pool, user_id).await? {
+        return Err(AppError::bad_request("Two-factor authentication is already enabled"));
+    }
+    let user = find_user(&pool, user_id).await?;
+    let secret = TwoFactor::begin_enrollment(&pool, user_id).await?;
+    Ok(HttpResponse::Ok().json(EnrollResponse {
+        otpauth_uri: otpauth_uri(&secret, &user.email),
+        secret,
+    }))
+}
+
+pub async fn confirm(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    body: web::Json<ConfirmRequest>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authenticate(&req, &pool).await?;
+    let two_factor = TwoFactor::find(&pool, user_id)
+        .await?
+        .ok_or_else(|| AppError::bad_request("Two-factor enrollment has not been started"))?;
+    if two_factor.is_enabled() {
+        return Err(AppError::bad_request("Two-factor authentication is already enabled"));
+    }
+    if !two_factor.check_code(&pool, &body.code).await? {
+        return Err(AppError::bad_request("Invalid verification code"));
+    }
+
+    TwoFactor::enable(&pool, user_id).await?;
+    let recovery_codes = RecoveryCode::regenerate(&pool, user_id).await?;
+    Ok(HttpResponse::Ok().json(RecoveryCodesResponse { recovery_codes }))
+}
+
+pub async fn disable(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    body: web::Json<DisableRequest>,
+) -> Result<HttpResponse, AppError> {
+    let auth = authenticate_context(&req, &pool).await?;
+    let user_id = auth.user_id;
+    let two_factor = TwoFactor::find(&pool, user_id)
+        .await?
+        .filter(|tf| tf.is_enabled())
+        .ok_or_else(|| AppError::bad_request("Two-factor authentication is not enabled"))?;
+
+    // The code below is the second factor, so it can't also stand in for a
+    // fresh sign-in
+    let user = find_user(&pool, user_id).await?;
+    confirm_identity(&pool, &user, auth, body.password.as_deref(), None).await?;
+    if !two_factor
+        .verify_second_factor(&pool, body.code.as_deref(), body.recovery_code.as_deref())
+        .await?
+    {
+        return Err(AppError::unauthorized("Invalid verification code"));
+    }
+
+    TwoFactor::disable(&pool, user_id).await?;
+    Ok(HttpResponse::Ok().json("Two-factor authentication disabled"))
+}
+
+pub async fn regenerate_recovery_codes(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    body: web::Json<ConfirmRequest>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authenticate(&req, &pool).await?;
+    let two_factor = TwoFactor::find(&pool, user_id)
+        .await?
+        .filter(|tf| tf.is_enabled())
+        .ok_or_else(|| AppError::bad_request("Two-factor authentication is not enabled"))?;
+    if !two_factor.check_code(&pool, &body.code).await? {
+        return Err(AppError::unauthorized("Invalid verification code"));
+    }
+
+    let recovery_codes = RecoveryCode::regenerate(&pool, user_id).await?;
+    Ok(HttpResponse::Ok().json(RecoveryCodesResponse { recovery_codes }))
+}
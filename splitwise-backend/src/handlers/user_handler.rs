@@ -1,9 +1,25 @@
 use actix_web::{web, HttpResponse, HttpRequest};
-use serde::Deserialize;
-use sqlx::PgPool;
-use crate::models::user::{User, UserResponse};
-use crate::utils::auth::{verify_jwt, extract_token_from_header};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+use uuid::Uuid;
+use crate::config::Config;
+use crate::handlers::auth_handler::send_verification_email;
+use crate::handlers::avatar_handler::delete_avatar_blobs;
+use crate::models::api_token::Scope;
+use crate::models::comment::ExpenseComment;
+use crate::models::expense::Expense;
+use crate::models::friendship::Friendship;
+use crate::models::group::Group;
+use crate::models::preferences::UserPreferences;
+use crate::models::session::Session;
+use crate::models::split::Split;
+use crate::models::user::{User, UserResponse, UserSearchResult};
+use crate::utils::auth::{authenticate, authenticate_context, authorize, confirm_identity, create_jwt, hash_password};
+use crate::utils::blob_store::BlobStore;
 use crate::utils::error::AppError;
+use crate::utils::helpers::{validate_email, validate_password};
+use crate::utils::mailer::{notify_user, Mailer, NotificationKind};
 
 #[derive(Debug, Deserialize)]
 pub struct UpdateProfileRequest {
@@ -11,13 +27,32 @@ pub struct UpdateProfileRequest {
     pub email: Option<String>,
 }
 
+#[derive(Debug, Serialize)]
+pub struct UpdateProfileResponse {
+    #[serde(flatten)]
+    pub user: UserResponse,
+    pub pending_email: Option<String>, // Takes effect once the link sent to it is opened
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChangePasswordRequest {
+    pub current_password: Option<String>, // Not needed when setting a first password
+    pub code: Option<String>, // Two-factor code, to confirm a first password without signing in again
+    pub new_password: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChangePasswordResponse {
+    pub token: String,
+}
+
 pub async fn get_current_user(
     req: HttpRequest,
     pool: web::Data<PgPool>,
 ) -> Result<HttpResponse, AppError> {
-    let user_id = extract_user_id_from_request(&req)?;
+    let user_id = authorize(&req, &pool, Scope::Read).await?;
     let user = sqlx::query_as::<_, User>(
-        r#"SELECT id, name, email, password_hash, created_at, updated_at FROM users WHERE id = $1"#,
+        r#"SELECT id, name, email, password_hash, email_verified_at, token_version, avatar_key, created_at, updated_at FROM users WHERE id = $1"#,
     )
     .bind(user_id)
     .fetch_one(pool.get_ref())
@@ -25,41 +60,325 @@ pub async fn get_current_user(
     Ok(HttpResponse::Ok().json(UserResponse::from(user)))
 }
 
-pub async fn get_user_profile(
+pub async fn update_current_user(
     req: HttpRequest,
     pool: web::Data<PgPool>,
-    path: web::Path<uuid::Uuid>,
+    mailer: web::Data<dyn Mailer>,
+    config: web::Data<Config>,
+    body: web::Json<UpdateProfileRequest>,
 ) -> Result<HttpResponse, AppError> {
-    let _ = extract_user_id_from_request(&req)?; // Auth check
-    let user_id = path.into_inner();
-    let user = sqlx::query_as::<_, User>(
-        r#"SELECT id, name, email, password_hash, created_at, updated_at FROM users WHERE id = $1"#,
+    let user_id = authenticate(&req, &pool).await?;
+    let mut user = User::find_by_id(&pool, user_id)
+        .await?
+        .ok_or_else(|| AppError::not_found("User not found"))?;
+
+    if let Some(name) = &body.name {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(AppError::bad_request("Name cannot be empty"));
+        }
+        user = User::update_name(&pool, user_id, name).await?;
+    }
+
+    // A new address only replaces the current one after it has been verified,
+    // so a typo here can't lock the user out of their account.
+    let mut pending_email = None;
+    if let Some(email) = &body.email {
+        let email = email.trim();
+        if email != user.email {
+            if !validate_email(email) {
+                return Err(AppError::bad_request("Invalid email address"));
+            }
+            if User::find_by_email(&pool, email).await?.is_some() {
+                return Err(AppError::conflict("Email is already in use"));
+            }
+            send_verification_email(&pool, mailer.get_ref(), &config, user_id, email).await?;
+            pending_email = Some(email.to_string());
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(UpdateProfileResponse {
+        user: UserResponse::from(user),
+        pending_email,
+    }))
+}
+
+/// Changes the caller's password and revokes every other token and session.
+/// A fresh token is returned so the client making the change stays signed in.
+pub async fn change_password(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    mailer: web::Data<dyn Mailer>,
+    body: web::Json<ChangePasswordRequest>,
+) -> Result<HttpResponse, AppError> {
+    let auth = authenticate_context(&req, &pool).await?;
+    let user_id = auth.user_id;
+    let user = User::find_by_id(&pool, user_id)
+        .await?
+        .ok_or_else(|| AppError::not_found("User not found"))?;
+
+    confirm_identity(&pool, &user, auth, body.current_password.as_deref(), body.code.as_deref()).await?;
+    if !validate_password(&body.new_password) {
+        return Err(AppError::bad_request("Password must be at least 8 characters long"));
+    }
+
+    let password_hash = hash_password(&body.new_password)
+        .map_err(|_| AppError::internal_error("Failed to hash password"))?;
+    let token_version = User::update_password(&pool, user_id, &password_hash).await?;
+    Session::revoke_all(&pool, user_id, auth.session_id).await?;
+    let token = create_jwt(user_id, token_version, auth.session_id)
+        .map_err(|_| AppError::internal_error("Failed to create token"))?;
+
+    notify_user(
+        &pool,
+        mailer.get_ref(),
+        user_id,
+        NotificationKind::Security,
+        "Your password was changed",
+        "The password for your account was just changed and all other devices were signed out. \
+         If this wasn't you, reset your password immediately.",
     )
-    .bind(user_id)
-    .fetch_one(pool.get_ref())
     .await?;
-    Ok(HttpResponse::Ok().json(UserResponse::from(user)))
+
+    Ok(HttpResponse::Ok().json(ChangePasswordResponse { token }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteAccountRequest {
+    pub password: Option<String>, // Required unless the account is passwordless
+    pub code: Option<String>, // Two-factor code, for passwordless accounts that haven't just signed in
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct ExportedGroup {
+    pub id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub created_by: Uuid,
+    pub role: String,
+    pub joined_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
 }
 
-pub async fn get_users(
+/// Everything we hold about a user, as returned by the data export endpoint.
+#[derive(Debug, Serialize)]
+pub struct AccountExport {
+    pub exported_at: DateTime<Utc>,
+    pub user: UserResponse,
+    pub preferences: UserPreferences,
+    pub groups: Vec<ExportedGroup>,
+    pub expenses: Vec<Expense>,
+    pub splits: Vec<Split>,
+    pub settlements: Vec<Split>, // The user's own splits that have been settled
+    pub comments: Vec<ExpenseComment>,
+}
+
+pub async fn delete_current_user(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    blob_store: web::Data<dyn BlobStore>,
+    body: web::Json<DeleteAccountRequest>,
+) -> Result<HttpResponse, AppError> {
+    let auth = authenticate_context(&req, &pool).await?;
+    let user_id = auth.user_id;
+    let user = User::find_by_id(&pool, user_id)
+        .await?
+        .ok_or_else(|| AppError::not_found("User not found"))?;
+
+    confirm_identity(&pool, &user, auth, body.password.as_deref(), body.code.as_deref()).await?;
+
+    User::anonymize(&pool, user_id).await?;
+    delete_avatar_blobs(blob_store.get_ref(), user.avatar_key).await;
+    Ok(HttpResponse::Ok().json("Account deleted"))
+}
+
+pub async fn export_current_user(
     req: HttpRequest,
     pool: web::Data<PgPool>,
 ) -> Result<HttpResponse, AppError> {
-    let _ = extract_user_id_from_request(&req)?; // Auth check
-    let users = sqlx::query_as::<_, User>(
-        r#"SELECT id, name, email, password_hash, created_at, updated_at FROM users"#,
+    let user_id = authenticate(&req, &pool).await?;
+    let user = User::find_by_id(&pool, user_id)
+        .await?
+        .ok_or_else(|| AppError::not_found("User not found"))?;
+
+    let groups = sqlx::query_as::<_, ExportedGroup>(
+        r#"
+        SELECT g.id, g.name, g.description, g.created_by,
+               COALESCE(gm.role, 'admin') AS role, gm.joined_at, g.created_at
+        FROM groups g
+        LEFT JOIN group_members gm ON gm.group_id = g.id AND gm.user_id = $1
+        WHERE gm.user_id IS NOT NULL OR g.created_by = $1
+        ORDER BY g.created_at
+        "#,
     )
+    .bind(user_id)
+    .fetch_all(pool.get_ref())
+    .await?;
+
+    let expenses = sqlx::query_as::<_, Expense>(
+        r#"
+        SELECT e.id, e.group_id, e.description, e.notes, e.amount::FLOAT8 AS amount, e.paid_by,
+               e.split_type, e.category_id, e.recurring_expense_id, e.expense_date, e.version, e.created_by, e.created_at, e.updated_at
+        FROM expenses e
+        WHERE e.deleted_at IS NULL
+          AND (e.paid_by = $1
+               OR e.created_by = $1
+               OR EXISTS (SELECT 1 FROM splits s WHERE s.expense_id = e.id AND s.user_id = $1))
+        ORDER BY e.expense_date, e.created_at
+        "#,
+    )
+    .bind(user_id)
     .fetch_all(pool.get_ref())
     .await?;
-    let responses: Vec<UserResponse> = users.into_iter().map(UserResponse::from).collect();
-    Ok(HttpResponse::Ok().json(responses))
-}
-
-fn extract_user_id_from_request(req: &HttpRequest) -> Result<uuid::Uuid, AppError> {
-    let auth_header = req.headers().get("Authorization").and_then(|v| v.to_str().ok());
-    let token = auth_header.and_then(|h| extract_token_from_header(h));
-    let claims = token
-        .ok_or_else(|| AppError::unauthorized("Missing token"))
-        .and_then(|t| verify_jwt(&t).map_err(AppError::from))?;
-    uuid::Uuid::parse_str(&claims.sub).map_err(|_| AppError::unauthorized("Invalid user id in token"))
-} 
\ No newline at end of file
+
+    let expense_ids: Vec<Uuid> = expenses.iter().map(|e| e.id).collect();
+    let splits = sqlx::query_as::<_, Split>(
+        r#"
+        SELECT id, expense_id, user_id, amount::FLOAT8 AS amount, COALESCE(is_settled, FALSE) AS is_settled,
+               settled_at, created_at, updated_at
+        FROM splits
+        WHERE expense_id = ANY($1)
+        ORDER BY created_at
+        "#,
+    )
+    .bind(&expense_ids)
+    .fetch_all(pool.get_ref())
+    .await?;
+    let settlements = splits
+        .iter()
+        .filter(|split| split.user_id == user_id && split.is_settled)
+        .cloned()
+        .collect();
+
+    let preferences = UserPreferences::find(&pool, user_id).await?;
+    let comments = ExpenseComment::list_by_author(&pool, user_id).await?;
+    let export = AccountExport {
+        exported_at: Utc::now(),
+        user: UserResponse::from(user),
+        preferences,
+        groups,
+        expenses,
+        splits,
+        settlements,
+        comments,
+    };
+    Ok(HttpResponse::Ok()
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"splitwise-export-{}.json\"", user_id),
+        ))
+        .json(export))
+}
+
+#[derive(Debug, Serialize)]
+pub struct MutualGroup {
+    pub id: Uuid,
+    pub name: String,
+}
+
+/// Another user's profile as seen by the caller. Only `id` and `name` are
+/// public; the rest is filled in when the two share a group or are friends.
+#[derive(Debug, Serialize)]
+pub struct UserProfileResponse {
+    pub id: Uuid,
+    pub name: String,
+    pub visibility: &'static str, // "full" or "public"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mutual_groups: Option<Vec<MutualGroup>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub net_balance: Option<f64>, // Positive when they owe the caller
+}
+
+pub async fn get_user_profile(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<uuid::Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let caller_id = authorize(&req, &pool, Scope::Read).await?;
+    let user_id = path.into_inner();
+    let user = User::find_by_id(&pool, user_id)
+        .await?
+        .ok_or_else(|| AppError::not_found("User not found"))?;
+
+    let mutual_groups = Group::shared_between(&pool, caller_id, user_id).await?;
+    let is_friend = Friendship::are_friends(&pool, caller_id, user_id).await?;
+    if mutual_groups.is_empty() && !is_friend && caller_id != user_id {
+        return Ok(HttpResponse::Ok().json(UserProfileResponse {
+            id: user.id,
+            name: user.name,
+            visibility: "public",
+            email: None,
+            created_at: None,
+            mutual_groups: None,
+            net_balance: None,
+        }));
+    }
+
+    let net_balance = Split::net_balance_between(&pool, caller_id, user_id).await?;
+    Ok(HttpResponse::Ok().json(UserProfileResponse {
+        id: user.id,
+        name: user.name,
+        visibility: "full",
+        email: Some(user.email),
+        created_at: Some(user.created_at),
+        mutual_groups: Some(
+            mutual_groups
+                .into_iter()
+                .map(|group| MutualGroup { id: group.id, name: group.name })
+                .collect(),
+        ),
+        net_balance: Some(net_balance),
+    }))
+}
+
+const SEARCH_DEFAULT_LIMIT: i64 = 20;
+const SEARCH_MAX_LIMIT: i64 = 50;
+const SEARCH_MIN_QUERY_LEN: usize = 2;
+
+#[derive(Debug, Deserialize)]
+pub struct UserSearchQuery {
+    pub q: String,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UserSearchResponse {
+    pub results: Vec<UserSearchResult>,
+    pub limit: i64,
+    pub offset: i64,
+    pub has_more: bool,
+}
+
+/// Finds people to add to a group by name prefix or exact email, without
+/// exposing the whole user table.
+pub async fn search_users(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    query: web::Query<UserSearchQuery>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = authorize(&req, &pool, Scope::Read).await?;
+
+    let q = query.q.trim();
+    if q.chars().count() < SEARCH_MIN_QUERY_LEN {
+        return Err(AppError::bad_request("Search query must be at least 2 characters"));
+    }
+    let limit = query.limit.unwrap_or(SEARCH_DEFAULT_LIMIT).clamp(1, SEARCH_MAX_LIMIT);
+    let offset = query.offset.unwrap_or(0).max(0);
+
+    // One extra row tells us whether there is another page
+    let mut results = User::search(&pool, user_id, q, limit + 1, offset).await?;
+    let has_more = results.len() as i64 > limit;
+    results.truncate(limit as usize);
+
+    Ok(HttpResponse::Ok().json(UserSearchResponse {
+        results,
+        limit,
+        offset,
+        has_more,
+    }))
+}
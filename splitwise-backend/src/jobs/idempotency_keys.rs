@@ -0,0 +1,15 @@
+use std::time::Duration;
+use sqlx::PgPool;
+use crate::models::idempotency_key::IdempotencyKey;
+
+/// Expired keys are only ever taken over by a request reusing them, so the
+/// rest are swept up here. Spawn it once on the actix runtime.
+pub async fn run(pool: PgPool, interval: Duration) {
+    let mut ticker = actix_web::rt::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        if let Err(e) = IdempotencyKey::delete_expired(&pool).await {
+            log::error!("Idempotency keys: {}", e);
+        }
+    }
+}
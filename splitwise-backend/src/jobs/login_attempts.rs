@@ -0,0 +1,15 @@
+use std::time::Duration;
+use sqlx::PgPool;
+use crate::models::login_attempt::LoginAttempt;
+
+/// Deletes login attempts older than `retention_days` every `interval`.
+/// Spawn it once on the actix runtime.
+pub async fn run(pool: PgPool, retention_days: i32, interval: Duration) {
+    let mut ticker = actix_web::rt::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        if let Err(e) = LoginAttempt::delete_older_than(&pool, retention_days).await {
+            log::error!("Login attempts: {}", e);
+        }
+    }
+}
@@ -0,0 +1,3 @@
+pub mod recurring_expenses;
+pub mod idempotency_keys;
+pub mod login_attempts;
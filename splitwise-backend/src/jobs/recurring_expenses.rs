@@ -0,0 +1,57 @@
+use std::time::Duration;
+use sqlx::PgPool;
+use crate::handlers::expense_handler::{insert_expense, validate_expense, CreateExpenseRequest};
+use crate::models::expense::Expense;
+use crate::models::recurring_expense::RecurringExpense;
+use crate::utils::error::AppError;
+
+/// Materializes due recurring expenses every `interval`, for as long as the
+/// server runs. Spawn it once on the actix runtime.
+pub async fn run(pool: PgPool, interval: Duration) {
+    let mut ticker = actix_web::rt::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        if let Err(e) = materialize_due(&pool).await {
+            log::error!("Recurring expenses: {}", e.message);
+        }
+    }
+}
+
+/// Creates every occurrence that has fallen due, catching up on any missed
+/// while the server was down. Safe to run concurrently or repeatedly: an
+/// occurrence is created at most once and the schedule only moves forward
+/// from the sequence it was read at.
+pub async fn materialize_due(pool: &PgPool) -> Result<(), AppError> {
+    for recurring in RecurringExpense::list_due(pool).await? {
+        let expense = CreateExpenseRequest::from(recurring.template());
+        if let Err(e) = validate_expense(pool, recurring.created_by, &expense).await {
+            // A template that no longer validates (say the payer is no longer
+            // a friend) is paused rather than retried every tick
+            log::warn!("Pausing recurring expense {}: {}", recurring.id, e.message);
+            recurring.set_paused(pool, true).await?;
+            continue;
+        }
+        if let Err(e) = materialize(pool, &recurring, &expense).await {
+            log::error!("Recurring expense {}: {}", recurring.id, e.message);
+        }
+    }
+    Ok(())
+}
+
+async fn materialize(
+    pool: &PgPool,
+    recurring: &RecurringExpense,
+    expense: &CreateExpenseRequest,
+) -> Result<(), AppError> {
+    let today = Expense::today(pool, recurring.group_id, recurring.created_by).await?;
+    let mut sequence = recurring.sequence;
+    while let Some(date) = recurring.occurrence(sequence).filter(|date| *date <= today) {
+        insert_expense(pool, recurring.created_by, expense, Some((recurring.id, date))).await?;
+        if !recurring.advance(pool, sequence, sequence + 1).await? {
+            // Another worker (or an edit, skip or pause) moved it first
+            return Ok(());
+        }
+        sequence += 1;
+    }
+    Ok(())
+}
@@ -3,6 +3,7 @@ pub mod routes;
 pub mod handlers;
 pub mod models;
 pub mod db;
+pub mod jobs;
 pub mod utils;
 
 pub use config::Config;
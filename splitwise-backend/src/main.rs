@@ -1,5 +1,12 @@
 use actix_web::{web, App, HttpServer, middleware};
+use actix_web::middleware::from_fn;
+use std::sync::Arc;
+use std::time::Duration;
 use splitwise_backend::{Config, establish_connection, routes::configure_routes};
+use splitwise_backend::jobs::{idempotency_keys, login_attempts, recurring_expenses};
+use splitwise_backend::utils::blob_store;
+use splitwise_backend::utils::idempotency::idempotency;
+use splitwise_backend::utils::mailer::{LogMailer, Mailer};
 // use tracing::info;
 // use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt};
 
@@ -10,6 +17,8 @@ async fn main() -> std::io::Result<()> {
 
     // Load configuration
     let config = Config::from_env().expect("Failed to load configuration");
+    splitwise_backend::config::setup_logger();
+    splitwise_backend::utils::jwt_keys::init(&config).expect("Failed to load JWT keys");
     
     // Establish database connection
     let pool = establish_connection(&config)
@@ -21,16 +30,37 @@ async fn main() -> std::io::Result<()> {
         .await
         .expect("Failed to run database migrations");
     
+    // Outgoing mail is only logged until a delivery transport is configured
+    let mailer: Arc<dyn Mailer> = Arc::new(LogMailer);
+    let blob_store = blob_store::from_config(&config).expect("Failed to set up blob storage");
+
+    // Recurring expenses are materialized in the background while the server runs
+    actix_web::rt::spawn(recurring_expenses::run(
+        pool.clone(),
+        Duration::from_secs(config.recurring_expense_interval_secs.max(1)),
+    ));
+    actix_web::rt::spawn(idempotency_keys::run(pool.clone(), Duration::from_secs(3600)));
+    actix_web::rt::spawn(login_attempts::run(
+        pool.clone(),
+        config.login_attempt_retention_days,
+        Duration::from_secs(3600),
+    ));
+
+    let bind_addr = (config.host.clone(), config.port);
     println!("Starting server at {}:{}", config.host, config.port);
     
     // Start HTTP server
     HttpServer::new(move || {
         App::new()
             .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::new(config.clone()))
+            .app_data(web::Data::from(mailer.clone()))
+            .app_data(web::Data::from(blob_store.clone()))
+            .wrap(from_fn(idempotency))
             .wrap(middleware::Logger::default())
             .configure(configure_routes)
     })
-    .bind((config.host, config.port))?
+    .bind(bind_addr)?
     .run()
     .await
 }
@@ -0,0 +1,174 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use crate::utils::auth::{generate_token, hash_token};
+
+/// Marks personal access tokens so they can be told apart from JWTs (and
+/// spotted by secret scanners) without a database lookup.
+pub const API_TOKEN_PREFIX: &str = "swp_";
+
+/// What a personal access token may do. Session tokens from `login` are not
+/// scoped and can do everything.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Scope {
+    #[serde(rename = "read")]
+    Read,
+    #[serde(rename = "expenses:write")]
+    ExpensesWrite,
+    #[serde(rename = "groups:write")]
+    GroupsWrite,
+    #[serde(rename = "friends:write")]
+    FriendsWrite,
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::Read => "read",
+            Scope::ExpensesWrite => "expenses:write",
+            Scope::GroupsWrite => "groups:write",
+            Scope::FriendsWrite => "friends:write",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Scope> {
+        match value {
+            "read" => Some(Scope::Read),
+            "expenses:write" => Some(Scope::ExpensesWrite),
+            "groups:write" => Some(Scope::GroupsWrite),
+            "friends:write" => Some(Scope::FriendsWrite),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, FromRow)]
+pub struct ApiToken {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    pub token_prefix: String,
+    pub token_hash: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ApiTokenResponse {
+    pub id: Uuid,
+    pub name: String,
+    pub token_prefix: String,
+    pub scopes: Vec<Scope>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+const COLUMNS: &str =
+    "id, user_id, name, token_prefix, token_hash, scopes, expires_at, last_used_at, revoked_at, created_at";
+
+impl ApiToken {
+    pub fn scopes(&self) -> Vec<Scope> {
+        self.scopes.iter().filter_map(|s| Scope::parse(s)).collect()
+    }
+
+    pub fn to_response(&self) -> ApiTokenResponse {
+        ApiTokenResponse {
+            id: self.id,
+            name: self.name.clone(),
+            token_prefix: self.token_prefix.clone(),
+            scopes: self.scopes(),
+            expires_at: self.expires_at,
+            last_used_at: self.last_used_at,
+            created_at: self.created_at,
+        }
+    }
+
+    /// Creates a token and returns it together with the raw value, which is
+    /// shown to the user once and never stored.
+    pub async fn create(
+        pool: &PgPool,
+        user_id: Uuid,
+        name: &str,
+        scopes: &[Scope],
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<(ApiToken, String), sqlx::Error> {
+        let raw = format!("{}{}", API_TOKEN_PREFIX, generate_token());
+        let prefix: String = raw.chars().take(API_TOKEN_PREFIX.len() + 8).collect();
+        let scopes: Vec<&str> = scopes.iter().map(Scope::as_str).collect();
+        let token = sqlx::query_as::<_, ApiToken>(&format!(
+            r#"
+            INSERT INTO api_tokens (user_id, name, token_prefix, token_hash, scopes, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING {}
+            "#,
+            COLUMNS
+        ))
+        .bind(user_id)
+        .bind(name)
+        .bind(prefix)
+        .bind(hash_token(&raw))
+        .bind(scopes)
+        .bind(expires_at)
+        .fetch_one(pool)
+        .await?;
+        Ok((token, raw))
+    }
+
+    /// Looks up a usable (unrevoked, unexpired) token by its raw value.
+    pub async fn find_active(pool: &PgPool, raw: &str) -> Result<Option<ApiToken>, sqlx::Error> {
+        sqlx::query_as::<_, ApiToken>(&format!(
+            r#"
+            SELECT {} FROM api_tokens
+            WHERE token_hash = $1 AND revoked_at IS NULL AND (expires_at IS NULL OR expires_at > NOW())
+            "#,
+            COLUMNS
+        ))
+        .bind(hash_token(raw))
+        .fetch_optional(pool)
+        .await
+    }
+
+    pub async fn list_active(pool: &PgPool, user_id: Uuid) -> Result<Vec<ApiToken>, sqlx::Error> {
+        sqlx::query_as::<_, ApiToken>(&format!(
+            r#"
+            SELECT {} FROM api_tokens
+            WHERE user_id = $1 AND revoked_at IS NULL AND (expires_at IS NULL OR expires_at > NOW())
+            ORDER BY created_at DESC
+            "#,
+            COLUMNS
+        ))
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Records that the token was used, at most once a minute.
+    pub async fn touch(pool: &PgPool, token_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE api_tokens SET last_used_at = NOW()
+            WHERE id = $1 AND (last_used_at IS NULL OR last_used_at < NOW() - INTERVAL '1 minute')
+            "#,
+        )
+        .bind(token_id)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn revoke(pool: &PgPool, user_id: Uuid, token_id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"UPDATE api_tokens SET revoked_at = NOW() WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL"#,
+        )
+        .bind(token_id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
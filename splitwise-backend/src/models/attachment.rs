@@ -0,0 +1,180 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::PgExecutor;
+use sqlx::{FromRow, PgPool};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ExpenseAttachment {
+    pub id: Uuid,
+    pub expense_id: Uuid,
+    pub uploaded_by: Uuid,
+    pub file_name: String,
+    pub content_type: String,
+    pub size_bytes: i32,
+    #[serde(skip_serializing)]
+    pub blob_key: String,
+    #[serde(skip_serializing)]
+    pub thumbnail_key: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// An attachment as listed to clients, with the URLs it is served from.
+/// Requests to them must be authenticated.
+#[derive(Debug, Serialize)]
+pub struct AttachmentResponse {
+    pub id: Uuid,
+    pub expense_id: Uuid,
+    pub uploaded_by: Uuid,
+    pub file_name: String,
+    pub content_type: String,
+    pub size_bytes: i32,
+    pub url: String,
+    pub thumbnail_url: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// An attachment whose blobs have been stored, ready to be recorded.
+pub struct NewAttachment<'a> {
+    pub id: Uuid,
+    pub expense_id: Uuid,
+    pub uploaded_by: Uuid,
+    pub file_name: &'a str,
+    pub content_type: &'a str,
+    pub size_bytes: i32,
+    pub blob_key: &'a str,
+    pub thumbnail_key: Option<&'a str>,
+}
+
+impl From<ExpenseAttachment> for AttachmentResponse {
+    fn from(attachment: ExpenseAttachment) -> Self {
+        let url = format!("/api/expenses/{}/attachments/{}", attachment.expense_id, attachment.id);
+        AttachmentResponse {
+            thumbnail_url: attachment.thumbnail_key.as_ref().map(|_| format!("{}?size=thumb", url)),
+            url,
+            id: attachment.id,
+            expense_id: attachment.expense_id,
+            uploaded_by: attachment.uploaded_by,
+            file_name: attachment.file_name,
+            content_type: attachment.content_type,
+            size_bytes: attachment.size_bytes,
+            created_at: attachment.created_at,
+        }
+    }
+}
+
+impl ExpenseAttachment {
+    /// Every blob stored for this attachment.
+    pub fn blob_keys(&self) -> Vec<String> {
+        std::iter::once(self.blob_key.clone())
+            .chain(self.thumbnail_key.clone())
+            .collect()
+    }
+
+    pub async fn list(pool: &PgPool, expense_id: Uuid) -> Result<Vec<ExpenseAttachment>, sqlx::Error> {
+        sqlx::query_as::<_, ExpenseAttachment>(
+            r#"SELECT * FROM expense_attachments WHERE expense_id = $1 ORDER BY created_at, id"#,
+        )
+        .bind(expense_id)
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn count(pool: &PgPool, expense_id: Uuid) -> Result<i64, sqlx::Error> {
+        sqlx::query_scalar(r#"SELECT COUNT(*) FROM expense_attachments WHERE expense_id = $1"#)
+            .bind(expense_id)
+            .fetch_one(pool)
+            .await
+    }
+
+    pub async fn find(pool: &PgPool, expense_id: Uuid, attachment_id: Uuid) -> Result<Option<ExpenseAttachment>, sqlx::Error> {
+        sqlx::query_as::<_, ExpenseAttachment>(
+            r#"SELECT * FROM expense_attachments WHERE id = $1 AND expense_id = $2"#,
+        )
+        .bind(attachment_id)
+        .bind(expense_id)
+        .fetch_optional(pool)
+        .await
+    }
+
+    /// Records the attachment unless the expense already has `max_per_expense`.
+    /// The expense row is locked while counting, so concurrent uploads can't
+    /// both squeeze in under the limit.
+    pub async fn create(
+        pool: &PgPool,
+        new: &NewAttachment<'_>,
+        max_per_expense: usize,
+    ) -> Result<Option<ExpenseAttachment>, sqlx::Error> {
+        let mut tx = pool.begin().await?;
+        sqlx::query(r#"SELECT id FROM expenses WHERE id = $1 FOR UPDATE"#)
+            .bind(new.expense_id)
+            .execute(&mut *tx)
+            .await?;
+        let existing: i64 = sqlx::query_scalar(r#"SELECT COUNT(*) FROM expense_attachments WHERE expense_id = $1"#)
+            .bind(new.expense_id)
+            .fetch_one(&mut *tx)
+            .await?;
+        if existing as usize >= max_per_expense {
+            return Ok(None);
+        }
+        let attachment = sqlx::query_as::<_, ExpenseAttachment>(
+            r#"
+            INSERT INTO expense_attachments
+                (id, expense_id, uploaded_by, file_name, content_type, size_bytes, blob_key, thumbnail_key)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            RETURNING *
+            "#,
+        )
+        .bind(new.id)
+        .bind(new.expense_id)
+        .bind(new.uploaded_by)
+        .bind(new.file_name)
+        .bind(new.content_type)
+        .bind(new.size_bytes)
+        .bind(new.blob_key)
+        .bind(new.thumbnail_key)
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(Some(attachment))
+    }
+
+    pub async fn delete(pool: &PgPool, attachment_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query(r#"DELETE FROM expense_attachments WHERE id = $1"#)
+            .bind(attachment_id)
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Removes the expense's attachments, returning the blob keys to clean up
+    /// once the surrounding transaction has committed.
+    pub async fn delete_for_expense<'e>(
+        executor: impl PgExecutor<'e>,
+        expense_id: Uuid,
+    ) -> Result<Vec<String>, sqlx::Error> {
+        let attachments = sqlx::query_as::<_, ExpenseAttachment>(
+            r#"DELETE FROM expense_attachments WHERE expense_id = $1 RETURNING *"#,
+        )
+        .bind(expense_id)
+        .fetch_all(executor)
+        .await?;
+        Ok(attachments.iter().flat_map(ExpenseAttachment::blob_keys).collect())
+    }
+
+    /// Blob keys of every attachment in the group, collected before the
+    /// group (and with it its expenses) is deleted.
+    pub async fn blob_keys_for_group(pool: &PgPool, group_id: Uuid) -> Result<Vec<String>, sqlx::Error> {
+        let attachments = sqlx::query_as::<_, ExpenseAttachment>(
+            r#"
+            SELECT a.* FROM expense_attachments a
+            JOIN expenses e ON e.id = a.expense_id
+            WHERE e.group_id = $1
+            "#,
+        )
+        .bind(group_id)
+        .fetch_all(pool)
+        .await?;
+        Ok(attachments.iter().flat_map(ExpenseAttachment::blob_keys).collect())
+    }
+}
@@ -0,0 +1,219 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+use uuid::Uuid;
+
+pub const MAX_CATEGORY_NAME_LEN: usize = 50;
+pub const MAX_KEYWORD_LEN: usize = 50;
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Category {
+    pub id: Uuid,
+    pub group_id: Option<Uuid>, // None for the predefined set
+    pub name: String,
+    pub created_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct CategoryRule {
+    pub id: Uuid,
+    pub group_id: Option<Uuid>, // None for built-in rules
+    pub keyword: String,
+    pub category_id: Uuid,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Category {
+    /// The predefined categories plus, when given, the group's own ones.
+    pub async fn list_available(pool: &PgPool, group_id: Option<Uuid>) -> Result<Vec<Category>, sqlx::Error> {
+        sqlx::query_as::<_, Category>(
+            r#"
+            SELECT id, group_id, name, created_by, created_at
+            FROM categories
+            WHERE group_id IS NULL OR group_id = $1
+            ORDER BY group_id NULLS FIRST, LOWER(name)
+            "#,
+        )
+        .bind(group_id)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Whether an expense in `group_id` (None for direct expenses) may use
+    /// this category.
+    pub async fn is_available(pool: &PgPool, category_id: Uuid, group_id: Option<Uuid>) -> Result<bool, sqlx::Error> {
+        sqlx::query_scalar(
+            r#"SELECT EXISTS (SELECT 1 FROM categories WHERE id = $1 AND (group_id IS NULL OR group_id = $2))"#,
+        )
+        .bind(category_id)
+        .bind(group_id)
+        .fetch_one(pool)
+        .await
+    }
+
+    pub async fn create(pool: &PgPool, group_id: Uuid, name: &str, created_by: Uuid) -> Result<Category, sqlx::Error> {
+        sqlx::query_as::<_, Category>(
+            r#"
+            INSERT INTO categories (group_id, name, created_by)
+            VALUES ($1, $2, $3)
+            RETURNING id, group_id, name, created_by, created_at
+            "#,
+        )
+        .bind(group_id)
+        .bind(name)
+        .bind(created_by)
+        .fetch_one(pool)
+        .await
+    }
+
+    /// Deletes one of the group's custom categories. Expenses using it fall
+    /// back to no category, and rules pointing at it go with it.
+    pub async fn delete(pool: &PgPool, group_id: Uuid, category_id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(r#"DELETE FROM categories WHERE id = $1 AND group_id = $2"#)
+            .bind(category_id)
+            .bind(group_id)
+            .execute(pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+impl CategoryRule {
+    /// Keywords are stored the way they are matched: lowercase words
+    /// separated by single spaces.
+    pub fn normalize_keyword(keyword: &str) -> String {
+        words(keyword).join(" ")
+    }
+
+    /// The group's own rules followed by the built-in ones.
+    pub async fn list_for_group(pool: &PgPool, group_id: Option<Uuid>) -> Result<Vec<CategoryRule>, sqlx::Error> {
+        sqlx::query_as::<_, CategoryRule>(
+            r#"
+            SELECT id, group_id, keyword, category_id, created_at
+            FROM category_rules
+            WHERE group_id IS NULL OR group_id = $1
+            ORDER BY group_id NULLS LAST, keyword
+            "#,
+        )
+        .bind(group_id)
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn create(
+        pool: &PgPool,
+        group_id: Uuid,
+        keyword: &str,
+        category_id: Uuid,
+    ) -> Result<CategoryRule, sqlx::Error> {
+        sqlx::query_as::<_, CategoryRule>(
+            r#"
+            INSERT INTO category_rules (group_id, keyword, category_id)
+            VALUES ($1, $2, $3)
+            RETURNING id, group_id, keyword, category_id, created_at
+            "#,
+        )
+        .bind(group_id)
+        .bind(keyword)
+        .bind(category_id)
+        .fetch_one(pool)
+        .await
+    }
+
+    pub async fn delete(pool: &PgPool, group_id: Uuid, rule_id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(r#"DELETE FROM category_rules WHERE id = $1 AND group_id = $2"#)
+            .bind(rule_id)
+            .bind(group_id)
+            .execute(pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Picks a category for `description` from the rules that apply to the
+    /// group. A keyword matches whole words only ("cab" doesn't match
+    /// "cabinet"); group rules beat built-in ones and longer keywords beat
+    /// shorter ones.
+    pub async fn suggest(pool: &PgPool, group_id: Option<Uuid>, description: &str) -> Result<Option<Category>, sqlx::Error> {
+        if words(description).is_empty() {
+            return Ok(None);
+        }
+        let rules = Self::list_for_group(pool, group_id).await?;
+        let Some(rule) = Self::best_match(&rules, description) else {
+            return Ok(None);
+        };
+        sqlx::query_as::<_, Category>(
+            r#"SELECT id, group_id, name, created_by, created_at FROM categories WHERE id = $1"#,
+        )
+        .bind(rule.category_id)
+        .fetch_optional(pool)
+        .await
+    }
+
+    /// The rule `suggest` goes by, if any matches.
+    fn best_match<'a>(rules: &'a [CategoryRule], description: &str) -> Option<&'a CategoryRule> {
+        let description = words(description);
+        rules
+            .iter()
+            .filter(|rule| {
+                let keyword = words(&rule.keyword);
+                !keyword.is_empty() && description.windows(keyword.len()).any(|window| window == keyword.as_slice())
+            })
+            .max_by_key(|rule| (rule.group_id.is_some(), rule.keyword.len()))
+    }
+}
+
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(group_id: Option<Uuid>, keyword: &str) -> CategoryRule {
+        CategoryRule {
+            id: Uuid::new_v4(),
+            group_id,
+            keyword: keyword.to_string(),
+            category_id: Uuid::new_v4(),
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn words_are_lowercase_and_split_on_punctuation() {
+        assert_eq!(words("  Uber-ride to the AIRPORT!! "), ["uber", "ride", "to", "the", "airport"]);
+        assert!(words("--- ...").is_empty());
+        assert_eq!(CategoryRule::normalize_keyword("  Movie   Night "), "movie night");
+    }
+
+    #[test]
+    fn keywords_match_whole_words_only() {
+        let rules = [rule(None, "cab")];
+        assert!(CategoryRule::best_match(&rules, "Cab home").is_some());
+        assert!(CategoryRule::best_match(&rules, "late-night cab.").is_some());
+        assert!(CategoryRule::best_match(&rules, "New cabinet").is_none());
+        assert!(CategoryRule::best_match(&rules, "Taxicab").is_none());
+    }
+
+    #[test]
+    fn multi_word_keywords_match_consecutive_words() {
+        let rules = [rule(None, "movie night")];
+        assert!(CategoryRule::best_match(&rules, "Friday movie night snacks").is_some());
+        assert!(CategoryRule::best_match(&rules, "Night at the movie").is_none());
+    }
+
+    #[test]
+    fn group_rules_beat_built_in_ones_then_longer_keywords_win() {
+        let group_id = Some(Uuid::new_v4());
+        let rules = [rule(None, "uber eats"), rule(group_id, "uber"), rule(None, "eats")];
+        assert_eq!(CategoryRule::best_match(&rules, "Uber Eats order").unwrap().keyword, "uber");
+
+        let rules = [rule(None, "uber"), rule(None, "uber eats")];
+        assert_eq!(CategoryRule::best_match(&rules, "Uber Eats order").unwrap().keyword, "uber eats");
+    }
+}
@@ -0,0 +1,139 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::PgExecutor;
+use sqlx::{FromRow, PgPool};
+use uuid::Uuid;
+
+pub const MAX_COMMENT_LEN: usize = 2000;
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct ExpenseComment {
+    pub id: Uuid,
+    pub expense_id: Uuid,
+    pub user_id: Uuid,
+    pub author_name: String,
+    pub body: String, // For system comments, "<author_name> <action>"
+    pub is_system: bool,
+    pub created_at: DateTime<Utc>,
+    pub edited_at: Option<DateTime<Utc>>,
+}
+
+const SELECT_COMMENT: &str = r#"
+    SELECT c.id, c.expense_id, c.user_id, u.name AS author_name,
+           CASE WHEN c.is_system THEN u.name || ' ' || c.body ELSE c.body END AS body,
+           c.is_system, c.created_at, c.edited_at
+    FROM expense_comments c
+    JOIN users u ON u.id = c.user_id
+"#;
+
+impl ExpenseComment {
+    pub async fn list(pool: &PgPool, expense_id: Uuid) -> Result<Vec<ExpenseComment>, sqlx::Error> {
+        sqlx::query_as::<_, ExpenseComment>(&format!(
+            "{} WHERE c.expense_id = $1 ORDER BY c.created_at, c.id",
+            SELECT_COMMENT
+        ))
+        .bind(expense_id)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Comments the user wrote themselves, for their data export.
+    pub async fn list_by_author(pool: &PgPool, user_id: Uuid) -> Result<Vec<ExpenseComment>, sqlx::Error> {
+        sqlx::query_as::<_, ExpenseComment>(&format!(
+            "{} WHERE c.user_id = $1 AND NOT c.is_system ORDER BY c.created_at, c.id",
+            SELECT_COMMENT
+        ))
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn find(pool: &PgPool, expense_id: Uuid, comment_id: Uuid) -> Result<Option<ExpenseComment>, sqlx::Error> {
+        sqlx::query_as::<_, ExpenseComment>(&format!(
+            "{} WHERE c.id = $1 AND c.expense_id = $2",
+            SELECT_COMMENT
+        ))
+        .bind(comment_id)
+        .bind(expense_id)
+        .fetch_optional(pool)
+        .await
+    }
+
+    pub async fn create(pool: &PgPool, expense_id: Uuid, user_id: Uuid, body: &str) -> Result<ExpenseComment, sqlx::Error> {
+        let id: Uuid = sqlx::query_scalar(
+            r#"INSERT INTO expense_comments (expense_id, user_id, body) VALUES ($1, $2, $3) RETURNING id"#,
+        )
+        .bind(expense_id)
+        .bind(user_id)
+        .bind(body)
+        .fetch_one(pool)
+        .await?;
+        Self::find(pool, expense_id, id)
+            .await?
+            .ok_or(sqlx::Error::RowNotFound)
+    }
+
+    /// Records a change to the expense. Only `action` is stored; the actor's
+    /// current name is put in front when the comment is read, so it doesn't
+    /// outlive a rename or the account's deletion. Runs on any executor so it
+    /// can join the transaction that made the change; the wall-clock
+    /// timestamp keeps several changes from one transaction in order.
+    pub async fn add_system<'e>(
+        executor: impl PgExecutor<'e>,
+        expense_id: Uuid,
+        actor_id: Uuid,
+        action: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO expense_comments (expense_id, user_id, body, is_system, created_at)
+            VALUES ($1, $2, $3, TRUE, clock_timestamp())
+            "#,
+        )
+        .bind(expense_id)
+        .bind(actor_id)
+        .bind(action)
+        .execute(executor)
+        .await?;
+        Ok(())
+    }
+
+    /// Edits the author's own comment. System comments can't be edited.
+    pub async fn update(
+        pool: &PgPool,
+        expense_id: Uuid,
+        comment_id: Uuid,
+        user_id: Uuid,
+        body: &str,
+    ) -> Result<Option<ExpenseComment>, sqlx::Error> {
+        let updated = sqlx::query(
+            r#"
+            UPDATE expense_comments SET body = $4, edited_at = NOW(), updated_at = NOW()
+            WHERE id = $1 AND expense_id = $2 AND user_id = $3 AND NOT is_system
+            "#,
+        )
+        .bind(comment_id)
+        .bind(expense_id)
+        .bind(user_id)
+        .bind(body)
+        .execute(pool)
+        .await?;
+        if updated.rows_affected() == 0 {
+            return Ok(None);
+        }
+        Self::find(pool, expense_id, comment_id).await
+    }
+
+    /// Deletes the author's own comment. System comments can't be deleted.
+    pub async fn delete(pool: &PgPool, expense_id: Uuid, comment_id: Uuid, user_id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"DELETE FROM expense_comments WHERE id = $1 AND expense_id = $2 AND user_id = $3 AND NOT is_system"#,
+        )
+        .bind(comment_id)
+        .bind(expense_id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
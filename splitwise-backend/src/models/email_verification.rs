@@ -0,0 +1,61 @@
+use sqlx::{FromRow, PgPool};
+use uuid::Uuid;
+use chrono::{DateTime, Duration, Utc};
+use crate::utils::auth::{generate_token, hash_token};
+
+pub const VERIFICATION_TOKEN_TTL_HOURS: i64 = 24;
+
+#[derive(Debug, FromRow)]
+pub struct EmailVerificationToken {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub email: String,
+    pub token_hash: String,
+    pub expires_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl EmailVerificationToken {
+    /// Issues a new token for `email` and returns the raw value to be mailed.
+    /// Any earlier unused tokens for the user are invalidated.
+    pub async fn issue(pool: &PgPool, user_id: Uuid, email: &str) -> Result<String, sqlx::Error> {
+        let token = generate_token();
+        let expires_at = Utc::now() + Duration::hours(VERIFICATION_TOKEN_TTL_HOURS);
+
+        let mut tx = pool.begin().await?;
+        sqlx::query(
+            r#"UPDATE email_verification_tokens SET used_at = NOW() WHERE user_id = $1 AND used_at IS NULL"#,
+        )
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(
+            r#"INSERT INTO email_verification_tokens (user_id, email, token_hash, expires_at) VALUES ($1, $2, $3, $4)"#,
+        )
+        .bind(user_id)
+        .bind(email)
+        .bind(hash_token(&token))
+        .bind(expires_at)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(token)
+    }
+
+    /// Marks a valid, unexpired token as used and returns it.
+    pub async fn consume(pool: &PgPool, token: &str) -> Result<Option<EmailVerificationToken>, sqlx::Error> {
+        sqlx::query_as::<_, EmailVerificationToken>(
+            r#"
+            UPDATE email_verification_tokens
+            SET used_at = NOW()
+            WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+            RETURNING id, user_id, email, token_hash, expires_at, used_at, created_at
+            "#,
+        )
+        .bind(hash_token(token))
+        .fetch_optional(pool)
+        .await
+    }
+}
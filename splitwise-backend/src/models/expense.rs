@@ -1,22 +1,111 @@
 use serde::{Deserialize, Serialize};
-use sqlx::FromRow;
+use sqlx::{FromRow, PgPool, Postgres, QueryBuilder};
 use uuid::Uuid;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
+use chrono_tz::Tz;
+use data_encoding::BASE64URL_NOPAD;
+use crate::models::preferences::UserPreferences;
 use crate::models::split::{Split, SplitResponse};
+use crate::utils::helpers::escape_like;
 
 #[derive(Debug, Serialize, Deserialize, FromRow)]
 pub struct Expense {
     pub id: Uuid,
-    pub group_id: Uuid,
+    pub group_id: Option<Uuid>, // None for direct expenses between friends
     pub description: String,
+    pub notes: Option<String>,
     pub amount: f64,
     pub paid_by: Uuid,
     pub split_type: String, // "equal", "percentage", "fixed"
+    pub category_id: Option<Uuid>,
+    pub recurring_expense_id: Option<Uuid>, // Set when created by a recurring expense
+    pub expense_date: NaiveDate, // When it happened, in the group's timezone
+    pub version: i32, // Served as the ETag
     pub created_by: Uuid,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+/// Columns decoded into an `Expense`, with the amount cast to a float.
+pub const EXPENSE_COLUMNS: &str = "id, group_id, description, notes, amount::FLOAT8 AS amount, paid_by, split_type, \
+    category_id, recurring_expense_id, expense_date, version, created_by, created_at, updated_at";
+
+/// Orders for expense listings. Ties are broken by creation time and id so
+/// pages never overlap or skip rows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExpenseSort {
+    #[default]
+    DateDesc,
+    DateAsc,
+    AmountDesc,
+    AmountAsc,
+    CreatedDesc,
+    CreatedAsc,
+}
+
+impl ExpenseSort {
+    fn is_descending(&self) -> bool {
+        matches!(self, ExpenseSort::DateDesc | ExpenseSort::AmountDesc | ExpenseSort::CreatedDesc)
+    }
+
+    /// The sort key columns, compared as a row for keyset pagination.
+    fn key_columns(&self) -> &'static str {
+        match self {
+            ExpenseSort::DateDesc | ExpenseSort::DateAsc => "e.expense_date, e.created_at, e.id",
+            ExpenseSort::AmountDesc | ExpenseSort::AmountAsc => "e.amount, e.created_at, e.id",
+            ExpenseSort::CreatedDesc | ExpenseSort::CreatedAsc => "e.created_at, e.id",
+        }
+    }
+}
+
+/// Which expenses to list. Without a group or friend, the listing covers
+/// every expense the user pays for or has a share in.
+#[derive(Debug, Default)]
+pub struct ExpenseFilter {
+    pub group_id: Option<Uuid>, // The caller must be a member
+    pub friend_id: Option<Uuid>, // Direct expenses shared with this friend
+    pub paid_by: Option<Uuid>,
+    pub participant_id: Option<Uuid>, // Pays for or has a share in
+    pub category_id: Option<Uuid>,
+    pub date_from: Option<NaiveDate>,
+    pub date_to: Option<NaiveDate>,
+    pub min_amount: Option<f64>,
+    pub max_amount: Option<f64>,
+    pub text: Option<String>, // Matched anywhere in the description
+}
+
+/// Where the previous page ended. Handed to clients as an opaque string.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExpenseCursor {
+    pub sort: ExpenseSort,
+    pub expense_date: NaiveDate,
+    pub amount: f64,
+    pub created_at: DateTime<Utc>,
+    pub id: Uuid,
+}
+
+impl ExpenseCursor {
+    pub fn after(expense: &Expense, sort: ExpenseSort) -> ExpenseCursor {
+        ExpenseCursor {
+            sort,
+            expense_date: expense.expense_date,
+            amount: expense.amount,
+            created_at: expense.created_at,
+            id: expense.id,
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        BASE64URL_NOPAD.encode(&serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    pub fn decode(cursor: &str) -> Option<ExpenseCursor> {
+        let bytes = BASE64URL_NOPAD.decode(cursor.as_bytes()).ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ExpenseWithSplits {
     pub expense: Expense,
@@ -26,11 +115,15 @@ pub struct ExpenseWithSplits {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ExpenseResponse {
     pub id: Uuid,
-    pub group_id: Uuid,
+    pub group_id: Option<Uuid>,
     pub description: String,
+    pub notes: Option<String>,
     pub amount: f64,
     pub paid_by: Uuid,
     pub split_type: String,
+    pub category_id: Option<Uuid>,
+    pub recurring_expense_id: Option<Uuid>,
+    pub expense_date: NaiveDate,
     pub created_by: Uuid,
     pub created_at: DateTime<Utc>,
     pub splits: Vec<SplitResponse>,
@@ -42,12 +135,180 @@ impl From<Expense> for ExpenseResponse {
             id: expense.id,
             group_id: expense.group_id,
             description: expense.description,
+            notes: expense.notes,
             amount: expense.amount,
             paid_by: expense.paid_by,
             split_type: expense.split_type,
+            category_id: expense.category_id,
+            recurring_expense_id: expense.recurring_expense_id,
+            expense_date: expense.expense_date,
             created_by: expense.created_by,
             created_at: expense.created_at,
             splits: Vec::new(), // Will be populated when fetching from database
         }
     }
-} 
\ No newline at end of file
+}
+
+impl Expense {
+    /// Today's date for a new expense: in the group's timezone, or for a
+    /// direct expense in the timezone of the person recording it.
+    pub async fn today(pool: &PgPool, group_id: Option<Uuid>, user_id: Uuid) -> Result<NaiveDate, sqlx::Error> {
+        let group_timezone: Option<String> = match group_id {
+            Some(group_id) => sqlx::query_scalar(r#"SELECT timezone FROM groups WHERE id = $1"#)
+                .bind(group_id)
+                .fetch_optional(pool)
+                .await?,
+            None => None,
+        };
+        let tz = match group_timezone.and_then(|timezone| timezone.parse::<Tz>().ok()) {
+            Some(tz) => tz,
+            None => UserPreferences::find(pool, user_id).await?.tz(),
+        };
+        Ok(Utc::now().with_timezone(&tz).date_naive())
+    }
+
+    /// Members of the expense's group can see it; for a direct expense, the
+    /// people on it. Deleted expenses are included so their comments stay
+    /// readable.
+    pub async fn is_visible_to(pool: &PgPool, expense_id: Uuid, user_id: Uuid) -> Result<bool, sqlx::Error> {
+        sqlx::query_scalar(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM expenses e
+                WHERE e.id = $1
+                  AND CASE WHEN e.group_id IS NOT NULL THEN
+                          EXISTS (SELECT 1 FROM group_members gm WHERE gm.group_id = e.group_id AND gm.user_id = $2)
+                      ELSE
+                          e.paid_by = $2 OR e.created_by = $2
+                          OR EXISTS (SELECT 1 FROM splits s WHERE s.expense_id = e.id AND s.user_id = $2)
+                      END
+            )
+            "#,
+        )
+        .bind(expense_id)
+        .bind(user_id)
+        .fetch_one(pool)
+        .await
+    }
+
+    pub async fn is_deleted(pool: &PgPool, expense_id: Uuid) -> Result<bool, sqlx::Error> {
+        sqlx::query_scalar(r#"SELECT deleted_at IS NOT NULL FROM expenses WHERE id = $1"#)
+            .bind(expense_id)
+            .fetch_one(pool)
+            .await
+    }
+
+    /// The expense unless it was deleted.
+    pub async fn find(pool: &PgPool, expense_id: Uuid) -> Result<Option<Expense>, sqlx::Error> {
+        sqlx::query_as::<_, Expense>(&format!(
+            "SELECT {} FROM expenses WHERE id = $1 AND deleted_at IS NULL",
+            EXPENSE_COLUMNS
+        ))
+        .bind(expense_id)
+        .fetch_optional(pool)
+        .await
+    }
+
+    /// One page of the user's expenses matching `filter`, continuing after
+    /// `cursor` when given. Deleted expenses are left out.
+    pub async fn list(
+        pool: &PgPool,
+        user_id: Uuid,
+        filter: &ExpenseFilter,
+        sort: ExpenseSort,
+        cursor: Option<&ExpenseCursor>,
+        limit: i64,
+    ) -> Result<Vec<Expense>, sqlx::Error> {
+        let mut query = QueryBuilder::<Postgres>::new(format!(
+            "SELECT {} FROM expenses e WHERE e.deleted_at IS NULL",
+            EXPENSE_COLUMNS
+        ));
+        if let Some(group_id) = filter.group_id {
+            query.push(" AND e.group_id = ").push_bind(group_id);
+        } else if let Some(friend_id) = filter.friend_id {
+            query.push(" AND e.group_id IS NULL");
+            push_involves(&mut query, user_id);
+            push_involves(&mut query, friend_id);
+        } else {
+            push_involves(&mut query, user_id);
+        }
+        if let Some(paid_by) = filter.paid_by {
+            query.push(" AND e.paid_by = ").push_bind(paid_by);
+        }
+        if let Some(participant_id) = filter.participant_id {
+            push_involves(&mut query, participant_id);
+        }
+        if let Some(category_id) = filter.category_id {
+            query.push(" AND e.category_id = ").push_bind(category_id);
+        }
+        if let Some(date_from) = filter.date_from {
+            query.push(" AND e.expense_date >= ").push_bind(date_from);
+        }
+        if let Some(date_to) = filter.date_to {
+            query.push(" AND e.expense_date <= ").push_bind(date_to);
+        }
+        if let Some(min_amount) = filter.min_amount {
+            query.push(" AND e.amount >= ").push_bind(min_amount).push("::NUMERIC");
+        }
+        if let Some(max_amount) = filter.max_amount {
+            query.push(" AND e.amount <= ").push_bind(max_amount).push("::NUMERIC");
+        }
+        if let Some(text) = &filter.text {
+            query
+                .push(" AND e.description ILIKE ")
+                .push_bind(format!("%{}%", escape_like(text)));
+        }
+
+        let direction = if sort.is_descending() { "DESC" } else { "ASC" };
+        if let Some(cursor) = cursor {
+            query
+                .push(format!(" AND ({}) ", sort.key_columns()))
+                .push(if sort.is_descending() { "<" } else { ">" })
+                .push(" (");
+            let mut key = query.separated(", ");
+            match sort {
+                ExpenseSort::DateDesc | ExpenseSort::DateAsc => {
+                    key.push_bind(cursor.expense_date);
+                }
+                ExpenseSort::AmountDesc | ExpenseSort::AmountAsc => {
+                    key.push_bind(cursor.amount).push_unseparated("::NUMERIC");
+                }
+                ExpenseSort::CreatedDesc | ExpenseSort::CreatedAsc => {}
+            }
+            key.push_bind(cursor.created_at).push_bind(cursor.id);
+            query.push(")");
+        }
+        let order: Vec<String> = sort
+            .key_columns()
+            .split(", ")
+            .map(|column| format!("{} {}", column, direction))
+            .collect();
+        query.push(format!(" ORDER BY {} LIMIT ", order.join(", "))).push_bind(limit);
+
+        query.build_query_as::<Expense>().fetch_all(pool).await
+    }
+
+    /// The payer and everyone with a split.
+    pub async fn participants(pool: &PgPool, expense_id: Uuid) -> Result<Vec<Uuid>, sqlx::Error> {
+        sqlx::query_scalar(
+            r#"
+            SELECT paid_by FROM expenses WHERE id = $1
+            UNION
+            SELECT user_id FROM splits WHERE expense_id = $1
+            "#,
+        )
+        .bind(expense_id)
+        .fetch_all(pool)
+        .await
+    }
+}
+
+/// Limits the listing to expenses `user_id` pays for or has a share in.
+fn push_involves(query: &mut QueryBuilder<'_, Postgres>, user_id: Uuid) {
+    query
+        .push(" AND (e.paid_by = ")
+        .push_bind(user_id)
+        .push(" OR EXISTS (SELECT 1 FROM splits s WHERE s.expense_id = e.id AND s.user_id = ")
+        .push_bind(user_id)
+        .push("))");
+}
@@ -0,0 +1,170 @@
+use serde::Serialize;
+use sqlx::{FromRow, PgPool};
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct Friendship {
+    pub id: Uuid,
+    pub requester_id: Uuid,
+    pub addressee_id: Uuid,
+    pub status: String, // "pending", "accepted"
+    pub created_at: DateTime<Utc>,
+    pub accepted_at: Option<DateTime<Utc>>,
+}
+
+/// A pending request as shown to either side of it.
+#[derive(Debug, Serialize, FromRow)]
+pub struct FriendRequest {
+    pub id: Uuid,
+    pub user_id: Uuid, // The other person
+    pub name: String,
+    pub incoming: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+/// An accepted friend with what they owe the caller across groups and
+/// direct expenses (negative when the caller owes them).
+#[derive(Debug, Serialize, FromRow)]
+pub struct Friend {
+    pub user_id: Uuid,
+    pub name: String,
+    pub email: String,
+    pub friends_since: Option<DateTime<Utc>>,
+    pub net_balance: f64,
+}
+
+const COLUMNS: &str = "id, requester_id, addressee_id, status, created_at, accepted_at";
+
+impl Friendship {
+    pub fn is_accepted(&self) -> bool {
+        self.status == "accepted"
+    }
+
+    /// The friendship or pending request between two users, in either direction.
+    pub async fn find_between(pool: &PgPool, user_id: Uuid, other_id: Uuid) -> Result<Option<Friendship>, sqlx::Error> {
+        sqlx::query_as::<_, Friendship>(&format!(
+            r#"
+            SELECT {} FROM friendships
+            WHERE (requester_id = $1 AND addressee_id = $2) OR (requester_id = $2 AND addressee_id = $1)
+            "#,
+            COLUMNS
+        ))
+        .bind(user_id)
+        .bind(other_id)
+        .fetch_optional(pool)
+        .await
+    }
+
+    pub async fn are_friends(pool: &PgPool, user_id: Uuid, other_id: Uuid) -> Result<bool, sqlx::Error> {
+        Ok(Self::find_between(pool, user_id, other_id)
+            .await?
+            .is_some_and(|friendship| friendship.is_accepted()))
+    }
+
+    pub async fn request(pool: &PgPool, requester_id: Uuid, addressee_id: Uuid) -> Result<Friendship, sqlx::Error> {
+        sqlx::query_as::<_, Friendship>(&format!(
+            r#"INSERT INTO friendships (requester_id, addressee_id) VALUES ($1, $2) RETURNING {}"#,
+            COLUMNS
+        ))
+        .bind(requester_id)
+        .bind(addressee_id)
+        .fetch_one(pool)
+        .await
+    }
+
+    /// Accepts a pending request addressed to `user_id`.
+    pub async fn accept(pool: &PgPool, request_id: Uuid, user_id: Uuid) -> Result<Option<Friendship>, sqlx::Error> {
+        sqlx::query_as::<_, Friendship>(&format!(
+            r#"
+            UPDATE friendships SET status = 'accepted', accepted_at = NOW()
+            WHERE id = $1 AND addressee_id = $2 AND status = 'pending'
+            RETURNING {}
+            "#,
+            COLUMNS
+        ))
+        .bind(request_id)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+    }
+
+    /// Declines (addressee) or cancels (requester) a pending request.
+    pub async fn delete_request(pool: &PgPool, request_id: Uuid, user_id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM friendships
+            WHERE id = $1 AND status = 'pending' AND (requester_id = $2 OR addressee_id = $2)
+            "#,
+        )
+        .bind(request_id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn remove(pool: &PgPool, user_id: Uuid, friend_id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM friendships
+            WHERE status = 'accepted'
+              AND ((requester_id = $1 AND addressee_id = $2) OR (requester_id = $2 AND addressee_id = $1))
+            "#,
+        )
+        .bind(user_id)
+        .bind(friend_id)
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn pending_for(pool: &PgPool, user_id: Uuid) -> Result<Vec<FriendRequest>, sqlx::Error> {
+        sqlx::query_as::<_, FriendRequest>(
+            r#"
+            SELECT f.id, u.id AS user_id, u.name, f.addressee_id = $1 AS incoming, f.created_at
+            FROM friendships f
+            JOIN users u ON u.id = CASE WHEN f.requester_id = $1 THEN f.addressee_id ELSE f.requester_id END
+            WHERE f.status = 'pending' AND (f.requester_id = $1 OR f.addressee_id = $1)
+            ORDER BY f.created_at DESC
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Accepted friends with their net balance, in a single query.
+    pub async fn friends_of(pool: &PgPool, user_id: Uuid) -> Result<Vec<Friend>, sqlx::Error> {
+        sqlx::query_as::<_, Friend>(
+            r#"
+            WITH friends AS (
+                SELECT CASE WHEN requester_id = $1 THEN addressee_id ELSE requester_id END AS friend_id,
+                       accepted_at
+                FROM friendships
+                WHERE status = 'accepted' AND (requester_id = $1 OR addressee_id = $1)
+            ),
+            balances AS (
+                SELECT CASE WHEN e.paid_by = $1 THEN s.user_id ELSE e.paid_by END AS friend_id,
+                       SUM(CASE WHEN e.paid_by = $1 THEN s.amount ELSE -s.amount END) AS net_balance
+                FROM splits s
+                JOIN expenses e ON e.id = s.expense_id
+                WHERE s.is_settled = FALSE
+                  AND e.deleted_at IS NULL
+                  AND s.user_id <> e.paid_by
+                  AND (e.paid_by = $1 OR s.user_id = $1)
+                GROUP BY 1
+            )
+            SELECT u.id AS user_id, u.name, u.email, f.accepted_at AS friends_since,
+                   COALESCE(b.net_balance, 0)::FLOAT8 AS net_balance
+            FROM friends f
+            JOIN users u ON u.id = f.friend_id
+            LEFT JOIN balances b ON b.friend_id = f.friend_id
+            ORDER BY LOWER(u.name), u.id
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+    }
+}
@@ -1,7 +1,8 @@
 use serde::{Deserialize, Serialize};
-use sqlx::FromRow;
+use sqlx::{FromRow, PgPool};
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
+use crate::utils::images::avatar_url;
 
 #[derive(Debug, Serialize, Deserialize, FromRow)]
 pub struct Group {
@@ -9,6 +10,10 @@ pub struct Group {
     pub name: String,
     pub description: Option<String>,
     pub created_by: Uuid,
+    #[serde(skip_serializing)]
+    pub avatar_key: Option<String>,
+    pub timezone: String, // Expense dates are calendar dates here
+    pub version: i32, // Served as the ETag
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -34,8 +39,12 @@ pub struct GroupResponse {
     pub name: String,
     pub description: Option<String>,
     pub created_by: Uuid,
+    pub timezone: String,
+    pub version: i32,
     pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
     pub member_count: i64,
+    pub avatar_url: Option<String>,
 }
 
 impl From<Group> for GroupResponse {
@@ -45,8 +54,65 @@ impl From<Group> for GroupResponse {
             name: group.name,
             description: group.description,
             created_by: group.created_by,
+            timezone: group.timezone,
+            version: group.version,
             created_at: group.created_at,
+            updated_at: group.updated_at,
             member_count: 0, // Will be set when fetching from database
+            avatar_url: avatar_url("groups", group.id, group.avatar_key.as_deref()),
+        }
+    }
+} 
+
+impl Group {
+    pub fn to_response(self, member_count: i64) -> GroupResponse {
+        GroupResponse {
+            member_count,
+            ..self.into()
         }
     }
-} 
\ No newline at end of file
+
+    /// The creator is a member too, until removed like anyone else.
+    pub async fn is_member(pool: &PgPool, group_id: Uuid, user_id: Uuid) -> Result<bool, sqlx::Error> {
+        sqlx::query_scalar(
+            r#"SELECT EXISTS (SELECT 1 FROM group_members WHERE group_id = $1 AND user_id = $2)"#,
+        )
+        .bind(group_id)
+        .bind(user_id)
+        .fetch_one(pool)
+        .await
+    }
+
+    /// Points the group at a new avatar (or none) and returns the previous key.
+    pub async fn set_avatar_key(pool: &PgPool, group_id: Uuid, avatar_key: Option<&str>) -> Result<Option<String>, sqlx::Error> {
+        sqlx::query_scalar(
+            r#"
+            UPDATE groups SET avatar_key = $2, version = version + 1, updated_at = NOW()
+            FROM (SELECT avatar_key AS previous FROM groups WHERE id = $1 FOR UPDATE) old
+            WHERE id = $1
+            RETURNING old.previous
+            "#,
+        )
+        .bind(group_id)
+        .bind(avatar_key)
+        .fetch_one(pool)
+        .await
+    }
+
+    /// Groups both users belong to.
+    pub async fn shared_between(pool: &PgPool, user_id: Uuid, other_id: Uuid) -> Result<Vec<Group>, sqlx::Error> {
+        sqlx::query_as::<_, Group>(
+            r#"
+            SELECT g.id, g.name, g.description, g.created_by, g.avatar_key, g.timezone, g.version, g.created_at, g.updated_at
+            FROM groups g
+            JOIN group_members a ON a.group_id = g.id AND a.user_id = $1
+            JOIN group_members b ON b.group_id = g.id AND b.user_id = $2
+            ORDER BY g.name
+            "#,
+        )
+        .bind(user_id)
+        .bind(other_id)
+        .fetch_all(pool)
+        .await
+    }
+}
@@ -0,0 +1,106 @@
+use sqlx::{FromRow, PgPool};
+use uuid::Uuid;
+
+/// How long a request may run before its key is considered abandoned.
+const ABANDONED_AFTER_MINUTES: i32 = 10;
+
+/// A request made with an `Idempotency-Key`. The response fields stay empty
+/// until the first request carrying the key has finished.
+#[derive(Debug, FromRow)]
+pub struct IdempotencyKey {
+    pub fingerprint: String,
+    pub response_status: Option<i16>,
+    pub response_content_type: Option<String>,
+    pub response_body: Option<Vec<u8>>,
+}
+
+impl IdempotencyKey {
+    /// Reserves `key` for this request. False when the user already holds the
+    /// key and it hasn't expired. An expired key is taken over, as is one whose
+    /// request never finished (the server went down while handling it).
+    pub async fn claim(
+        pool: &PgPool,
+        user_id: Uuid,
+        key: &str,
+        fingerprint: &str,
+        ttl_secs: u64,
+    ) -> Result<bool, sqlx::Error> {
+        let claimed = sqlx::query(
+            r#"
+            INSERT INTO idempotency_keys (user_id, key, fingerprint, expires_at)
+            VALUES ($1, $2, $3, NOW() + make_interval(secs => $4))
+            ON CONFLICT (user_id, key) DO UPDATE
+            SET fingerprint = EXCLUDED.fingerprint, response_status = NULL, response_content_type = NULL,
+                response_body = NULL, created_at = NOW(), expires_at = EXCLUDED.expires_at
+            WHERE idempotency_keys.expires_at <= NOW()
+               OR (idempotency_keys.response_status IS NULL
+                   AND idempotency_keys.created_at <= NOW() - make_interval(mins => $5))
+            "#,
+        )
+        .bind(user_id)
+        .bind(key)
+        .bind(fingerprint)
+        .bind(ttl_secs as f64)
+        .bind(ABANDONED_AFTER_MINUTES)
+        .execute(pool)
+        .await?;
+        Ok(claimed.rows_affected() == 1)
+    }
+
+    pub async fn find(pool: &PgPool, user_id: Uuid, key: &str) -> Result<Option<IdempotencyKey>, sqlx::Error> {
+        sqlx::query_as::<_, IdempotencyKey>(
+            r#"
+            SELECT fingerprint, response_status, response_content_type, response_body
+            FROM idempotency_keys
+            WHERE user_id = $1 AND key = $2 AND expires_at > NOW()
+            "#,
+        )
+        .bind(user_id)
+        .bind(key)
+        .fetch_optional(pool)
+        .await
+    }
+
+    /// Stores the response to replay for the rest of the key's lifetime.
+    pub async fn complete(
+        pool: &PgPool,
+        user_id: Uuid,
+        key: &str,
+        status: u16,
+        content_type: Option<&str>,
+        body: &[u8],
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE idempotency_keys
+            SET response_status = $3, response_content_type = $4, response_body = $5
+            WHERE user_id = $1 AND key = $2
+            "#,
+        )
+        .bind(user_id)
+        .bind(key)
+        .bind(status as i16)
+        .bind(content_type)
+        .bind(body)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Gives the key up so the request can be retried with it.
+    pub async fn release(pool: &PgPool, user_id: Uuid, key: &str) -> Result<(), sqlx::Error> {
+        sqlx::query(r#"DELETE FROM idempotency_keys WHERE user_id = $1 AND key = $2"#)
+            .bind(user_id)
+            .bind(key)
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn delete_expired(pool: &PgPool) -> Result<u64, sqlx::Error> {
+        let deleted = sqlx::query(r#"DELETE FROM idempotency_keys WHERE expires_at <= NOW()"#)
+            .execute(pool)
+            .await?;
+        Ok(deleted.rows_affected())
+    }
+}
@@ -0,0 +1,110 @@
+use sqlx::{FromRow, PgPool};
+use uuid::Uuid;
+use chrono::{DateTime, Duration, Utc};
+use crate::utils::auth::{generate_token, hash_token};
+
+/// How long a user has to finish signing in at the identity provider.
+pub const OIDC_STATE_TTL_MINUTES: i64 = 10;
+
+#[derive(Debug, FromRow)]
+pub struct UserIdentity {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub provider: String,
+    pub subject: String,
+    pub email: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_login_at: DateTime<Utc>,
+}
+
+impl UserIdentity {
+    pub async fn find(pool: &PgPool, provider: &str, subject: &str) -> Result<Option<UserIdentity>, sqlx::Error> {
+        sqlx::query_as::<_, UserIdentity>(
+            r#"
+            SELECT id, user_id, provider, subject, email, created_at, last_login_at
+            FROM user_identities
+            WHERE provider = $1 AND subject = $2
+            "#,
+        )
+        .bind(provider)
+        .bind(subject)
+        .fetch_optional(pool)
+        .await
+    }
+
+    pub async fn link(
+        pool: &PgPool,
+        user_id: Uuid,
+        provider: &str,
+        subject: &str,
+        email: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"INSERT INTO user_identities (user_id, provider, subject, email) VALUES ($1, $2, $3, $4)"#,
+        )
+        .bind(user_id)
+        .bind(provider)
+        .bind(subject)
+        .bind(email)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn record_login(&self, pool: &PgPool, email: Option<&str>) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"UPDATE user_identities SET last_login_at = NOW(), email = COALESCE($2, email) WHERE id = $1"#,
+        )
+        .bind(self.id)
+        .bind(email)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+}
+
+/// A pending authorization request, keyed by its `state` parameter.
+pub struct OidcLoginState;
+
+impl OidcLoginState {
+    /// Starts an authorization request and returns `(state, code_verifier,
+    /// login_secret)`. The login secret goes only to the client that asked,
+    /// which must present it along with `state` to finish the login.
+    pub async fn create(pool: &PgPool) -> Result<(String, String, String), sqlx::Error> {
+        let state = generate_token();
+        let code_verifier = generate_token();
+        let login_secret = generate_token();
+        sqlx::query(
+            r#"INSERT INTO oidc_login_states (state_hash, code_verifier, login_secret_hash, expires_at) VALUES ($1, $2, $3, $4)"#,
+        )
+        .bind(hash_token(&state))
+        .bind(&code_verifier)
+        .bind(hash_token(&login_secret))
+        .bind(Utc::now() + Duration::minutes(OIDC_STATE_TTL_MINUTES))
+        .execute(pool)
+        .await?;
+
+        // Nothing else cleans these up, and abandoned logins are common
+        sqlx::query(r#"DELETE FROM oidc_login_states WHERE expires_at < NOW()"#)
+            .execute(pool)
+            .await?;
+        Ok((state, code_verifier, login_secret))
+    }
+
+    /// Consumes `state`, returning the PKCE verifier it was issued with, if
+    /// `login_secret` is the one issued alongside it. Each state can only be
+    /// used once.
+    pub async fn consume(pool: &PgPool, state: &str, login_secret: &str) -> Result<Option<String>, sqlx::Error> {
+        sqlx::query_scalar(
+            r#"
+            DELETE FROM oidc_login_states
+            WHERE state_hash = $1 AND login_secret_hash = $2 AND expires_at > NOW()
+            RETURNING code_verifier
+            "#,
+        )
+        .bind(hash_token(state))
+        .bind(hash_token(login_secret))
+        .fetch_optional(pool)
+        .await
+    }
+}
@@ -0,0 +1,249 @@
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+use chrono::{DateTime, Duration, Utc};
+
+// Failed attempts allowed before backoff kicks in. Per-IP is more lenient
+// since several people can share an address behind NAT.
+const FREE_ATTEMPTS_PER_ACCOUNT: i64 = 5;
+const FREE_ATTEMPTS_PER_IP: i64 = 20;
+// Each failure past the free allowance doubles the wait, up to the lockout cap
+const BASE_DELAY_SECONDS: i64 = 30;
+const MAX_LOCKOUT_SECONDS: i64 = 15 * 60;
+// Failures older than this no longer count towards a lockout
+const ATTEMPT_WINDOW_HOURS: i64 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoginOutcome {
+    Success,
+    Failure,
+    Blocked,
+}
+
+impl LoginOutcome {
+    fn as_str(&self) -> &'static str {
+        match self {
+            LoginOutcome::Success => "success",
+            LoginOutcome::Failure => "failure",
+            LoginOutcome::Blocked => "blocked",
+        }
+    }
+}
+
+/// Whether a login attempt may go ahead.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoginCheck {
+    Allowed(Uuid), // The attempt, recorded as a failure until settled
+    Locked(i64), // Seconds to wait
+}
+
+pub struct LoginAttempt;
+
+impl LoginAttempt {
+    /// Attempts are keyed on the normalized email so "Bob@x.com" and
+    /// "bob@x.com " share a counter.
+    pub fn normalize_email(email: &str) -> String {
+        email.trim().to_lowercase()
+    }
+
+    pub async fn record(
+        pool: &PgPool,
+        email: &str,
+        ip_address: Option<&str>,
+        user_id: Option<Uuid>,
+        outcome: LoginOutcome,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"INSERT INTO login_attempts (email, ip_address, user_id, outcome) VALUES ($1, $2, $3, $4)"#,
+        )
+        .bind(Self::normalize_email(email))
+        .bind(ip_address)
+        .bind(user_id)
+        .bind(outcome.as_str())
+        .execute(pool)
+        .await?;
+        if outcome == LoginOutcome::Blocked {
+            log::warn!("Blocked login attempt for {} from {}", email, ip_address.unwrap_or("unknown address"));
+        }
+        Ok(())
+    }
+
+    /// Drops attempts older than `retention_days`; they have long stopped
+    /// counting towards a lockout and only keep emails and addresses around.
+    pub async fn delete_older_than(pool: &PgPool, retention_days: i32) -> Result<u64, sqlx::Error> {
+        let deleted = sqlx::query(r#"DELETE FROM login_attempts WHERE created_at < NOW() - make_interval(days => $1)"#)
+            .bind(retention_days.max(1))
+            .execute(pool)
+            .await?;
+        Ok(deleted.rows_affected())
+    }
+
+    /// Starts an attempt on the account. Unless the account or the address is
+    /// locked, the attempt is recorded straight away as a failure, to be
+    /// settled with `finish` (or `discard`) once the outcome is known. The
+    /// check and the record happen under a per-account advisory lock, so
+    /// parallel guesses each count against the lockout before any of them
+    /// reaches the password check. An attempt that is never settled, because
+    /// of an error along the way, stays a failure.
+    pub async fn begin(pool: &PgPool, email: &str, ip_address: Option<&str>) -> Result<LoginCheck, sqlx::Error> {
+        let email = Self::normalize_email(email);
+        let mut tx = pool.begin().await?;
+        sqlx::query(r#"SELECT pg_advisory_xact_lock(hashtextextended('login_attempts:' || $1, 0))"#)
+            .bind(&email)
+            .execute(&mut *tx)
+            .await?;
+        if let Some(retry_after) = Self::retry_after(&mut tx, &email, ip_address).await? {
+            return Ok(LoginCheck::Locked(retry_after));
+        }
+        let id: Uuid = sqlx::query_scalar(
+            r#"INSERT INTO login_attempts (email, ip_address, outcome) VALUES ($1, $2, 'failure') RETURNING id"#,
+        )
+        .bind(&email)
+        .bind(ip_address)
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(LoginCheck::Allowed(id))
+    }
+
+    /// Settles an attempt started with `begin`.
+    pub async fn finish(
+        pool: &PgPool,
+        attempt_id: Uuid,
+        user_id: Option<Uuid>,
+        outcome: LoginOutcome,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(r#"UPDATE login_attempts SET user_id = $2, outcome = $3 WHERE id = $1"#)
+            .bind(attempt_id)
+            .bind(user_id)
+            .bind(outcome.as_str())
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Drops an attempt started with `begin` that turned out to be neither a
+    /// success nor a failure, such as a right password still awaiting its
+    /// second factor.
+    pub async fn discard(pool: &PgPool, attempt_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query(r#"DELETE FROM login_attempts WHERE id = $1"#)
+            .bind(attempt_id)
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Returns how many seconds the caller must wait before trying again, or
+    /// `None` if neither the account nor the IP address is currently locked.
+    async fn retry_after(
+        conn: &mut PgConnection,
+        email: &str,
+        ip_address: Option<&str>,
+    ) -> Result<Option<i64>, sqlx::Error> {
+        // A successful login clears the account's counter, but not the IP's:
+        // otherwise an attacker could reset it by signing into their own account.
+        let (account_failures, account_last): (i64, Option<DateTime<Utc>>) = sqlx::query_as(
+            r#"
+            SELECT COUNT(*), MAX(created_at)
+            FROM login_attempts
+            WHERE email = $1
+              AND outcome = 'failure'
+              AND created_at > NOW() - make_interval(hours => $2)
+              AND created_at > COALESCE(
+                  (SELECT MAX(created_at) FROM login_attempts WHERE email = $1 AND outcome = 'success'),
+                  '-infinity'
+              )
+            "#,
+        )
+        .bind(email)
+        .bind(ATTEMPT_WINDOW_HOURS as i32)
+        .fetch_one(&mut *conn)
+        .await?;
+
+        let (ip_failures, ip_last): (i64, Option<DateTime<Utc>>) = match ip_address {
+            Some(ip) => {
+                sqlx::query_as(
+                    r#"
+                    SELECT COUNT(*), MAX(created_at)
+                    FROM login_attempts
+                    WHERE ip_address = $1
+                      AND outcome = 'failure'
+                      AND created_at > NOW() - make_interval(hours => $2)
+                    "#,
+                )
+                .bind(ip)
+                .bind(ATTEMPT_WINDOW_HOURS as i32)
+                .fetch_one(&mut *conn)
+                .await?
+            }
+            None => (0, None),
+        };
+
+        let now = Utc::now();
+        Ok([
+            wait_seconds(account_failures, account_last, FREE_ATTEMPTS_PER_ACCOUNT, now),
+            wait_seconds(ip_failures, ip_last, FREE_ATTEMPTS_PER_IP, now),
+        ]
+        .into_iter()
+        .flatten()
+        .max())
+    }
+}
+
+/// Seconds left until the next attempt is allowed, given the failures
+/// counted within the window and when the last of them happened.
+fn wait_seconds(failures: i64, last_failure: Option<DateTime<Utc>>, free_attempts: i64, now: DateTime<Utc>) -> Option<i64> {
+    let last_failure = last_failure?;
+    if now - last_failure >= Duration::hours(ATTEMPT_WINDOW_HOURS) {
+        return None;
+    }
+    let millis = (locked_until(failures, last_failure, free_attempts)? - now).num_milliseconds();
+    (millis > 0).then(|| (millis + 999) / 1000)
+}
+
+fn locked_until(failures: i64, last_failure: DateTime<Utc>, free_attempts: i64) -> Option<DateTime<Utc>> {
+    if failures < free_attempts {
+        return None;
+    }
+    let exponent = (failures - free_attempts).min(16) as u32;
+    let delay = (BASE_DELAY_SECONDS * 2i64.pow(exponent)).min(MAX_LOCKOUT_SECONDS);
+    Some(last_failure + Duration::seconds(delay))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wait_after(failures: i64, seconds_ago: i64) -> Option<i64> {
+        let now = Utc::now();
+        wait_seconds(failures, Some(now - Duration::seconds(seconds_ago)), FREE_ATTEMPTS_PER_ACCOUNT, now)
+    }
+
+    #[test]
+    fn free_attempts_dont_lock() {
+        for failures in 0..FREE_ATTEMPTS_PER_ACCOUNT {
+            assert_eq!(wait_after(failures, 0), None);
+        }
+        assert_eq!(wait_seconds(0, None, FREE_ATTEMPTS_PER_ACCOUNT, Utc::now()), None);
+    }
+
+    #[test]
+    fn each_failure_past_the_allowance_doubles_the_wait() {
+        assert_eq!(wait_after(FREE_ATTEMPTS_PER_ACCOUNT, 0), Some(30));
+        assert_eq!(wait_after(FREE_ATTEMPTS_PER_ACCOUNT + 1, 0), Some(60));
+        assert_eq!(wait_after(FREE_ATTEMPTS_PER_ACCOUNT + 2, 0), Some(120));
+        assert_eq!(wait_after(FREE_ATTEMPTS_PER_ACCOUNT + 2, 100), Some(20));
+    }
+
+    #[test]
+    fn wait_is_capped_at_fifteen_minutes() {
+        assert_eq!(wait_after(FREE_ATTEMPTS_PER_ACCOUNT + 5, 0), Some(MAX_LOCKOUT_SECONDS));
+        assert_eq!(wait_after(1_000, 0), Some(MAX_LOCKOUT_SECONDS));
+    }
+
+    #[test]
+    fn lockout_ends_with_its_delay_and_the_window() {
+        assert_eq!(wait_after(1_000, MAX_LOCKOUT_SECONDS - 1), Some(1));
+        assert_eq!(wait_after(1_000, MAX_LOCKOUT_SECONDS), None);
+        assert_eq!(wait_after(1_000, ATTEMPT_WINDOW_HOURS * 3600 + 1), None);
+    }
+}
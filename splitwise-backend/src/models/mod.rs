@@ -1,4 +1,19 @@
 pub mod user;
 pub mod group;
 pub mod expense;
-pub mod split; 
\ No newline at end of file
+pub mod split;
+pub mod email_verification;
+pub mod two_factor;
+pub mod login_attempt;
+pub mod session;
+pub mod api_token;
+pub mod identity;
+pub mod preferences;
+pub mod friendship;
+pub mod category;
+pub mod recurring_expense;
+pub mod comment;
+pub mod attachment;
+pub mod revision;
+pub mod search;
+pub mod idempotency_key; 
\ No newline at end of file
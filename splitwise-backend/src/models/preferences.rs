@@ -0,0 +1,128 @@
+use chrono::NaiveDate;
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+use uuid::Uuid;
+use crate::utils::helpers::format_currency;
+
+/// Date formats users can pick from, mapped to their chrono patterns.
+pub const DATE_FORMATS: &[(&str, &str)] = &[
+    ("YYYY-MM-DD", "%Y-%m-%d"),
+    ("DD/MM/YYYY", "%d/%m/%Y"),
+    ("MM/DD/YYYY", "%m/%d/%Y"),
+    ("DD.MM.YYYY", "%d.%m.%Y"),
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct UserPreferences {
+    pub currency: String,
+    pub locale: String,
+    pub timezone: String,
+    pub date_format: String,
+    pub notify_group_activity: bool,
+    pub notify_expense_activity: bool,
+}
+
+impl Default for UserPreferences {
+    fn default() -> Self {
+        UserPreferences {
+            currency: "USD".to_string(),
+            locale: "en-US".to_string(),
+            timezone: "UTC".to_string(),
+            date_format: "YYYY-MM-DD".to_string(),
+            notify_group_activity: true,
+            notify_expense_activity: true,
+        }
+    }
+}
+
+impl UserPreferences {
+    /// Returns an error message describing the first invalid field.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.currency.len() != 3 || !self.currency.chars().all(|c| c.is_ascii_uppercase()) {
+            return Err("Currency must be a three-letter ISO 4217 code such as USD");
+        }
+        if !is_valid_locale(&self.locale) {
+            return Err("Locale must be a language tag such as en-US");
+        }
+        if self.timezone.parse::<Tz>().is_err() {
+            return Err("Timezone must be an IANA name such as Europe/Berlin");
+        }
+        if !DATE_FORMATS.iter().any(|(name, _)| *name == self.date_format) {
+            return Err("Date format must be one of YYYY-MM-DD, DD/MM/YYYY, MM/DD/YYYY, DD.MM.YYYY");
+        }
+        Ok(())
+    }
+
+    pub fn tz(&self) -> Tz {
+        self.timezone.parse().unwrap_or(Tz::UTC)
+    }
+
+    pub fn format_amount(&self, amount: f64) -> String {
+        format_currency(amount, &self.currency, &self.locale)
+    }
+
+    pub fn format_date(&self, date: NaiveDate) -> String {
+        let pattern = DATE_FORMATS
+            .iter()
+            .find(|(name, _)| *name == self.date_format)
+            .map_or("%Y-%m-%d", |(_, pattern)| *pattern);
+        date.format(pattern).to_string()
+    }
+
+    /// The user's preferences, or the defaults if they never saved any.
+    pub async fn find(pool: &PgPool, user_id: Uuid) -> Result<UserPreferences, sqlx::Error> {
+        let preferences = sqlx::query_as::<_, UserPreferences>(
+            r#"
+            SELECT currency, locale, timezone, date_format, notify_group_activity, notify_expense_activity
+            FROM user_preferences
+            WHERE user_id = $1
+            "#,
+        )
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+        Ok(preferences.unwrap_or_default())
+    }
+
+    pub async fn save(&self, pool: &PgPool, user_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO user_preferences
+                (user_id, currency, locale, timezone, date_format, notify_group_activity, notify_expense_activity)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT (user_id) DO UPDATE
+            SET currency = EXCLUDED.currency,
+                locale = EXCLUDED.locale,
+                timezone = EXCLUDED.timezone,
+                date_format = EXCLUDED.date_format,
+                notify_group_activity = EXCLUDED.notify_group_activity,
+                notify_expense_activity = EXCLUDED.notify_expense_activity,
+                updated_at = NOW()
+            "#,
+        )
+        .bind(user_id)
+        .bind(&self.currency)
+        .bind(&self.locale)
+        .bind(&self.timezone)
+        .bind(&self.date_format)
+        .bind(self.notify_group_activity)
+        .bind(self.notify_expense_activity)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+}
+
+/// Accepts `ll` or `ll-RR` (e.g. `hi`, `en-IN`, `de-DE`).
+fn is_valid_locale(locale: &str) -> bool {
+    let mut parts = locale.split('-');
+    let language_ok = parts
+        .next()
+        .is_some_and(|l| (2..=3).contains(&l.len()) && l.chars().all(|c| c.is_ascii_lowercase()));
+    let region_ok = match parts.next() {
+        Some(region) => region.len() == 2 && region.chars().all(|c| c.is_ascii_uppercase()),
+        None => true,
+    };
+    language_ok && region_ok && parts.next().is_none()
+}
@@ -0,0 +1,366 @@
+use chrono::{DateTime, Days, Months, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use sqlx::{FromRow, PgPool};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+impl Frequency {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Frequency::Daily => "daily",
+            Frequency::Weekly => "weekly",
+            Frequency::Monthly => "monthly",
+            Frequency::Yearly => "yearly",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Frequency> {
+        match value {
+            "daily" => Some(Frequency::Daily),
+            "weekly" => Some(Frequency::Weekly),
+            "monthly" => Some(Frequency::Monthly),
+            "yearly" => Some(Frequency::Yearly),
+            _ => None,
+        }
+    }
+
+    /// Date of occurrence `n`, counted from `start`. Monthly and yearly
+    /// schedules are anchored to `start`, so one starting on the 31st lands
+    /// on the last day of shorter months and returns to the 31st afterwards.
+    pub fn nth(&self, start: NaiveDate, n: u32) -> Option<NaiveDate> {
+        match self {
+            Frequency::Daily => start.checked_add_days(Days::new(n as u64)),
+            Frequency::Weekly => start.checked_add_days(Days::new(7 * n as u64)),
+            Frequency::Monthly => start.checked_add_months(Months::new(n)),
+            Frequency::Yearly => start.checked_add_months(Months::new(12 * n)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SplitDefinition {
+    pub user_id: Uuid,
+    pub amount: f64,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct RecurringExpense {
+    pub id: Uuid,
+    pub group_id: Option<Uuid>,
+    pub description: String,
+    pub amount: f64,
+    pub paid_by: Uuid,
+    pub split_type: String,
+    pub category_id: Option<Uuid>,
+    pub splits: Json<Vec<SplitDefinition>>,
+    pub frequency: String,
+    pub start_date: NaiveDate,
+    pub end_date: Option<NaiveDate>,
+    #[serde(skip_serializing)]
+    pub sequence: i32,
+    pub next_occurrence: Option<NaiveDate>, // None once the schedule has ended
+    pub is_paused: bool,
+    pub created_by: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Fields of a recurring expense that apply to every future occurrence.
+#[derive(Debug)]
+pub struct RecurringExpenseTemplate {
+    pub group_id: Option<Uuid>,
+    pub description: String,
+    pub amount: f64,
+    pub paid_by: Uuid,
+    pub split_type: String,
+    pub category_id: Option<Uuid>,
+    pub splits: Vec<SplitDefinition>,
+}
+
+const COLUMNS: &str = "id, group_id, description, amount::FLOAT8 AS amount, paid_by, split_type, category_id, \
+    splits, frequency, start_date, end_date, sequence, next_occurrence, is_paused, created_by, created_at, updated_at";
+
+impl RecurringExpense {
+    pub fn frequency(&self) -> Frequency {
+        Frequency::parse(&self.frequency).unwrap_or(Frequency::Monthly)
+    }
+
+    /// Date of occurrence `sequence`, or None when it falls after the end date.
+    pub fn occurrence(&self, sequence: i32) -> Option<NaiveDate> {
+        let date = self.frequency().nth(self.start_date, u32::try_from(sequence).ok()?)?;
+        match self.end_date {
+            Some(end_date) if date > end_date => None,
+            _ => Some(date),
+        }
+    }
+
+    pub fn template(&self) -> RecurringExpenseTemplate {
+        RecurringExpenseTemplate {
+            group_id: self.group_id,
+            description: self.description.clone(),
+            amount: self.amount,
+            paid_by: self.paid_by,
+            split_type: self.split_type.clone(),
+            category_id: self.category_id,
+            splits: self.splits.0.clone(),
+        }
+    }
+
+    pub async fn create(
+        pool: &PgPool,
+        created_by: Uuid,
+        template: &RecurringExpenseTemplate,
+        frequency: Frequency,
+        start_date: NaiveDate,
+        end_date: Option<NaiveDate>,
+    ) -> Result<RecurringExpense, sqlx::Error> {
+        sqlx::query_as::<_, RecurringExpense>(&format!(
+            r#"
+            INSERT INTO recurring_expenses
+                (group_id, description, amount, paid_by, split_type, category_id, splits,
+                 frequency, start_date, end_date, next_occurrence, created_by)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $9, $11)
+            RETURNING {}
+            "#,
+            COLUMNS
+        ))
+        .bind(template.group_id)
+        .bind(&template.description)
+        .bind(template.amount)
+        .bind(template.paid_by)
+        .bind(&template.split_type)
+        .bind(template.category_id)
+        .bind(Json(&template.splits))
+        .bind(frequency.as_str())
+        .bind(start_date)
+        .bind(end_date)
+        .bind(created_by)
+        .fetch_one(pool)
+        .await
+    }
+
+    pub async fn find(pool: &PgPool, id: Uuid) -> Result<Option<RecurringExpense>, sqlx::Error> {
+        sqlx::query_as::<_, RecurringExpense>(&format!(
+            "SELECT {} FROM recurring_expenses WHERE id = $1",
+            COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+    }
+
+    /// Whether the user pays or shares in each occurrence.
+    pub fn involves(&self, user_id: Uuid) -> bool {
+        self.paid_by == user_id || self.splits.0.iter().any(|split| split.user_id == user_id)
+    }
+
+    /// Recurring expenses the user created, takes part in, or that belong
+    /// to their groups.
+    pub async fn list_visible(
+        pool: &PgPool,
+        user_id: Uuid,
+        group_id: Option<Uuid>,
+    ) -> Result<Vec<RecurringExpense>, sqlx::Error> {
+        sqlx::query_as::<_, RecurringExpense>(&format!(
+            r#"
+            SELECT {} FROM recurring_expenses r
+            WHERE (r.created_by = $1
+                   OR r.paid_by = $1
+                   OR r.splits @> jsonb_build_array(jsonb_build_object('user_id', $1))
+                   OR r.group_id IN (SELECT group_id FROM group_members WHERE user_id = $1))
+              AND ($2::UUID IS NULL OR r.group_id = $2)
+            ORDER BY r.next_occurrence NULLS LAST, r.created_at
+            "#,
+            COLUMNS
+        ))
+        .bind(user_id)
+        .bind(group_id)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Active schedules whose next occurrence is due, judged by the calendar
+    /// date in the group's timezone, or the creator's for direct expenses.
+    pub async fn list_due(pool: &PgPool) -> Result<Vec<RecurringExpense>, sqlx::Error> {
+        sqlx::query_as::<_, RecurringExpense>(&format!(
+            r#"
+            SELECT {} FROM recurring_expenses r
+            WHERE NOT r.is_paused
+              AND r.next_occurrence <= (
+                  NOW() AT TIME ZONE COALESCE(
+                      (SELECT timezone FROM groups g WHERE g.id = r.group_id),
+                      (SELECT timezone FROM user_preferences p WHERE p.user_id = r.created_by),
+                      'UTC'
+                  )
+              )::DATE
+            ORDER BY r.next_occurrence
+            "#,
+            COLUMNS
+        ))
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Moves the schedule to occurrence `to_sequence`, unless someone else
+    /// already moved it on from `from_sequence`. Returns whether it moved.
+    pub async fn advance(&self, pool: &PgPool, from_sequence: i32, to_sequence: i32) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            UPDATE recurring_expenses
+            SET sequence = $3, next_occurrence = $4, updated_at = NOW()
+            WHERE id = $1 AND sequence = $2
+            "#,
+        )
+        .bind(self.id)
+        .bind(from_sequence)
+        .bind(to_sequence)
+        .bind(self.occurrence(to_sequence))
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Changes what future occurrences look like; expenses already created
+    /// are left alone. The schedule itself is kept, with the next occurrence
+    /// recomputed against the new end date. Returns None if the schedule moved
+    /// on from the occurrence it was read at, since the recomputed date would
+    /// then be stale.
+    pub async fn update(
+        &self,
+        pool: &PgPool,
+        template: &RecurringExpenseTemplate,
+        end_date: Option<NaiveDate>,
+    ) -> Result<Option<RecurringExpense>, sqlx::Error> {
+        let next_occurrence = RecurringExpense { end_date, ..self.clone() }.occurrence(self.sequence);
+        sqlx::query_as::<_, RecurringExpense>(&format!(
+            r#"
+            UPDATE recurring_expenses
+            SET description = $2, amount = $3, paid_by = $4, split_type = $5, category_id = $6,
+                splits = $7, end_date = $8, next_occurrence = $9, updated_at = NOW()
+            WHERE id = $1 AND sequence = $10
+            RETURNING {}
+            "#,
+            COLUMNS
+        ))
+        .bind(self.id)
+        .bind(&template.description)
+        .bind(template.amount)
+        .bind(template.paid_by)
+        .bind(&template.split_type)
+        .bind(template.category_id)
+        .bind(Json(&template.splits))
+        .bind(end_date)
+        .bind(next_occurrence)
+        .bind(self.sequence)
+        .fetch_optional(pool)
+        .await
+    }
+
+    pub async fn set_paused(&self, pool: &PgPool, is_paused: bool) -> Result<RecurringExpense, sqlx::Error> {
+        sqlx::query_as::<_, RecurringExpense>(&format!(
+            r#"
+            UPDATE recurring_expenses SET is_paused = $2, updated_at = NOW()
+            WHERE id = $1
+            RETURNING {}
+            "#,
+            COLUMNS
+        ))
+        .bind(self.id)
+        .bind(is_paused)
+        .fetch_one(pool)
+        .await
+    }
+
+    pub async fn delete(&self, pool: &PgPool) -> Result<(), sqlx::Error> {
+        sqlx::query(r#"DELETE FROM recurring_expenses WHERE id = $1"#)
+            .bind(self.id)
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn schedule(frequency: Frequency, start_date: NaiveDate, end_date: Option<NaiveDate>) -> RecurringExpense {
+        RecurringExpense {
+            id: Uuid::new_v4(),
+            group_id: None,
+            description: "Rent".to_string(),
+            amount: 100.0,
+            paid_by: Uuid::new_v4(),
+            split_type: "equal".to_string(),
+            category_id: None,
+            splits: Json(Vec::new()),
+            frequency: frequency.as_str().to_string(),
+            start_date,
+            end_date,
+            sequence: 0,
+            next_occurrence: Some(start_date),
+            is_paused: false,
+            created_by: Uuid::new_v4(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn daily_and_weekly_count_days_from_start() {
+        let start = date(2024, 12, 30);
+        assert_eq!(Frequency::Daily.nth(start, 0), Some(start));
+        assert_eq!(Frequency::Daily.nth(start, 3), Some(date(2025, 1, 2)));
+        assert_eq!(Frequency::Weekly.nth(start, 2), Some(date(2025, 1, 13)));
+    }
+
+    #[test]
+    fn monthly_on_the_31st_lands_on_the_last_day_of_shorter_months() {
+        let start = date(2024, 1, 31);
+        let dates: Vec<_> = (0..5).map(|n| Frequency::Monthly.nth(start, n).unwrap()).collect();
+        assert_eq!(
+            dates,
+            [date(2024, 1, 31), date(2024, 2, 29), date(2024, 3, 31), date(2024, 4, 30), date(2024, 5, 31)]
+        );
+        assert_eq!(Frequency::Monthly.nth(date(2025, 1, 31), 1), Some(date(2025, 2, 28)));
+    }
+
+    #[test]
+    fn yearly_on_a_leap_day_returns_to_it_in_leap_years() {
+        let start = date(2024, 2, 29);
+        assert_eq!(Frequency::Yearly.nth(start, 1), Some(date(2025, 2, 28)));
+        assert_eq!(Frequency::Yearly.nth(start, 4), Some(date(2028, 2, 29)));
+    }
+
+    #[test]
+    fn frequency_round_trips_through_its_name() {
+        for frequency in [Frequency::Daily, Frequency::Weekly, Frequency::Monthly, Frequency::Yearly] {
+            assert_eq!(Frequency::parse(frequency.as_str()), Some(frequency));
+        }
+        assert_eq!(Frequency::parse("fortnightly"), None);
+    }
+
+    #[test]
+    fn occurrences_stop_after_the_end_date() {
+        let recurring = schedule(Frequency::Monthly, date(2024, 1, 31), Some(date(2024, 3, 31)));
+        assert_eq!(recurring.occurrence(2), Some(date(2024, 3, 31)));
+        assert_eq!(recurring.occurrence(3), None);
+        assert_eq!(recurring.occurrence(-1), None);
+
+        let open_ended = schedule(Frequency::Weekly, date(2024, 1, 1), None);
+        assert_eq!(open_ended.occurrence(52), Some(date(2024, 12, 30)));
+    }
+}
@@ -0,0 +1,140 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::types::Json;
+use sqlx::{FromRow, PgConnection, PgPool};
+use uuid::Uuid;
+
+/// One field of an expense before and after a change. `from` is null for a
+/// newly created expense.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub from: Value,
+    pub to: Value,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct ExpenseRevision {
+    pub id: Uuid,
+    pub expense_id: Uuid,
+    pub revision: i32,
+    pub changed_by: Uuid,
+    pub changed_by_name: String,
+    pub action: String, // "created", "updated", "deleted"
+    pub changes: Json<Vec<FieldChange>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct SplitSnapshot {
+    pub user_id: Uuid,
+    pub amount: f64,
+}
+
+/// The parts of an expense that revisions track.
+#[derive(Debug, Serialize)]
+pub struct ExpenseSnapshot {
+    pub description: String,
+    pub notes: Option<String>,
+    pub amount: f64,
+    pub paid_by: Uuid,
+    pub split_type: String,
+    pub category_id: Option<Uuid>,
+    pub expense_date: NaiveDate,
+    pub splits: Vec<SplitSnapshot>, // Ordered by user
+}
+
+impl ExpenseSnapshot {
+    /// Reads the expense as it stands within the caller's transaction.
+    pub async fn load(conn: &mut PgConnection, expense_id: Uuid) -> Result<ExpenseSnapshot, sqlx::Error> {
+        let (description, notes, amount, paid_by, split_type, category_id, expense_date) = sqlx::query_as(
+            r#"
+            SELECT description, notes, amount::FLOAT8, paid_by, split_type, category_id, expense_date
+            FROM expenses WHERE id = $1
+            "#,
+        )
+        .bind(expense_id)
+        .fetch_one(&mut *conn)
+        .await?;
+        let splits = sqlx::query_as::<_, SplitSnapshot>(
+            r#"SELECT user_id, amount::FLOAT8 AS amount FROM splits WHERE expense_id = $1 ORDER BY user_id"#,
+        )
+        .bind(expense_id)
+        .fetch_all(&mut *conn)
+        .await?;
+        Ok(ExpenseSnapshot { description, notes, amount, paid_by, split_type, category_id, expense_date, splits })
+    }
+
+    fn fields(&self) -> Vec<(&'static str, Value)> {
+        vec![
+            ("description", Value::from(self.description.as_str())),
+            ("notes", self.notes.as_deref().into()),
+            ("amount", Value::from(self.amount)),
+            ("paid_by", Value::from(self.paid_by.to_string())),
+            ("split_type", Value::from(self.split_type.as_str())),
+            ("category_id", self.category_id.map(|id| id.to_string()).into()),
+            ("expense_date", Value::from(self.expense_date.to_string())),
+            ("splits", serde_json::to_value(&self.splits).unwrap_or_default()),
+        ]
+    }
+
+    /// Fields that differ from `before`, or every field when there is no
+    /// `before` because the expense is new.
+    pub fn diff(&self, before: Option<&ExpenseSnapshot>) -> Vec<FieldChange> {
+        let old_fields = before.map(ExpenseSnapshot::fields);
+        self.fields()
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, (field, to))| {
+                let from = old_fields.as_ref().map_or(Value::Null, |fields| fields[i].1.clone());
+                (from != to).then(|| FieldChange { field: field.to_string(), from, to })
+            })
+            .collect()
+    }
+}
+
+impl ExpenseRevision {
+    /// Oldest first.
+    pub async fn list(pool: &PgPool, expense_id: Uuid) -> Result<Vec<ExpenseRevision>, sqlx::Error> {
+        sqlx::query_as::<_, ExpenseRevision>(
+            r#"
+            SELECT r.id, r.expense_id, r.revision, r.changed_by, u.name AS changed_by_name,
+                   r.action, r.changes, r.created_at
+            FROM expense_revisions r
+            JOIN users u ON u.id = r.changed_by
+            WHERE r.expense_id = $1
+            ORDER BY r.revision
+            "#,
+        )
+        .bind(expense_id)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Appends the next revision. Call it in the transaction that made the
+    /// change, after the expense row was written: that row's lock keeps
+    /// concurrent changes from claiming the same revision number.
+    pub async fn record(
+        conn: &mut PgConnection,
+        expense_id: Uuid,
+        changed_by: Uuid,
+        action: &str,
+        changes: &[FieldChange],
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO expense_revisions (expense_id, revision, changed_by, action, changes)
+            SELECT $1, COALESCE(MAX(revision), 0) + 1, $2, $3, $4
+            FROM expense_revisions WHERE expense_id = $1
+            "#,
+        )
+        .bind(expense_id)
+        .bind(changed_by)
+        .bind(action)
+        .bind(Json(changes))
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,74 @@
+use chrono::NaiveDate;
+use serde::Serialize;
+use sqlx::{FromRow, PgPool};
+use uuid::Uuid;
+
+/// Wrap matched words in snippets. Private-use characters, so they can't be
+/// confused with anything ts_headline copies from the text.
+pub const HIGHLIGHT_START: char = '\u{E000}';
+pub const HIGHLIGHT_END: char = '\u{E001}';
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct SearchHit {
+    pub kind: String, // "expense", "comment" or "group"
+    pub id: Uuid,
+    pub expense_id: Option<Uuid>, // The expense, or the one commented on
+    pub group_id: Option<Uuid>,
+    pub title: String, // Expense description or group name
+    pub snippet: String, // Matches wrapped in HIGHLIGHT_START/HIGHLIGHT_END
+    pub rank: f32,
+    pub expense_date: Option<NaiveDate>,
+}
+
+/// Searches expense descriptions and notes, comments and group names with
+/// Postgres full-text search (`websearch_to_tsquery` syntax: words, "quoted
+/// phrases", -excluded). Only groups the user belongs to are searched, plus
+/// direct expenses they take part in; deleted expenses are left out.
+pub async fn search(pool: &PgPool, user_id: Uuid, query: &str, limit: i64) -> Result<Vec<SearchHit>, sqlx::Error> {
+    let headline_options = format!(
+        "StartSel={}, StopSel={}, MaxWords=25, MinWords=8, MaxFragments=2, FragmentDelimiter=\" … \"",
+        HIGHLIGHT_START, HIGHLIGHT_END
+    );
+    sqlx::query_as::<_, SearchHit>(
+        r#"
+        WITH q AS (SELECT websearch_to_tsquery('english', $2) AS query),
+        my_groups AS (
+            SELECT group_id AS id FROM group_members WHERE user_id = $1
+        ),
+        my_expenses AS (
+            SELECT e.* FROM expenses e
+            WHERE e.deleted_at IS NULL
+              AND (e.group_id IN (SELECT id FROM my_groups)
+                   OR (e.group_id IS NULL
+                       AND (e.paid_by = $1
+                            OR EXISTS (SELECT 1 FROM splits s WHERE s.expense_id = e.id AND s.user_id = $1))))
+        )
+        SELECT 'expense' AS kind, e.id, e.id AS expense_id, e.group_id, e.description AS title,
+               ts_headline('english', e.description || COALESCE(E'\n' || e.notes, ''), q.query, $4) AS snippet,
+               ts_rank(e.search_vector, q.query) AS rank, e.expense_date
+        FROM my_expenses e, q
+        WHERE e.search_vector @@ q.query
+        UNION ALL
+        SELECT 'comment', c.id, e.id, e.group_id, e.description,
+               ts_headline('english', c.body, q.query, $4),
+               ts_rank(c.search_vector, q.query), e.expense_date
+        FROM expense_comments c
+        JOIN my_expenses e ON e.id = c.expense_id, q
+        WHERE c.search_vector @@ q.query AND NOT c.is_system
+        UNION ALL
+        SELECT 'group', g.id, NULL, g.id, g.name,
+               ts_headline('english', g.name || COALESCE(E'\n' || g.description, ''), q.query, $4),
+               ts_rank(g.search_vector, q.query), NULL
+        FROM groups g, q
+        WHERE g.search_vector @@ q.query AND g.id IN (SELECT id FROM my_groups)
+        ORDER BY rank DESC, expense_date DESC NULLS LAST, id
+        LIMIT $3
+        "#,
+    )
+    .bind(user_id)
+    .bind(query)
+    .bind(limit)
+    .bind(headline_options)
+    .fetch_all(pool)
+    .await
+}
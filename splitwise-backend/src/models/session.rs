@@ -0,0 +1,149 @@
+use serde::Serialize;
+use sqlx::{FromRow, PgPool};
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct Session {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub device_name: Option<String>,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SessionResponse {
+    pub id: Uuid,
+    pub device_name: Option<String>,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: DateTime<Utc>,
+    pub current: bool, // Whether this is the session making the request
+}
+
+impl Session {
+    pub fn to_response(self, current_session: Option<Uuid>) -> SessionResponse {
+        SessionResponse {
+            current: current_session == Some(self.id),
+            id: self.id,
+            device_name: self.device_name,
+            user_agent: self.user_agent,
+            ip_address: self.ip_address,
+            created_at: self.created_at,
+            last_used_at: self.last_used_at,
+        }
+    }
+
+    pub async fn create(
+        pool: &PgPool,
+        user_id: Uuid,
+        device_name: Option<&str>,
+        user_agent: Option<&str>,
+        ip_address: Option<&str>,
+    ) -> Result<Session, sqlx::Error> {
+        sqlx::query_as::<_, Session>(
+            r#"
+            INSERT INTO sessions (user_id, device_name, user_agent, ip_address)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id, user_id, device_name, user_agent, ip_address, created_at, last_used_at, revoked_at
+            "#,
+        )
+        .bind(user_id)
+        .bind(device_name)
+        .bind(user_agent)
+        .bind(ip_address)
+        .fetch_one(pool)
+        .await
+    }
+
+    /// Whether the session exists, belongs to the user and hasn't been revoked.
+    pub async fn is_active(pool: &PgPool, user_id: Uuid, session_id: Uuid) -> Result<bool, sqlx::Error> {
+        let active: Option<bool> = sqlx::query_scalar(
+            r#"SELECT TRUE FROM sessions WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL"#,
+        )
+        .bind(session_id)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+        Ok(active.is_some())
+    }
+
+    /// Whether the session is active and was signed in to within the last
+    /// `minutes`. Refreshing a token keeps its session, so this is the time
+    /// of the last actual sign-in.
+    pub async fn signed_in_within(pool: &PgPool, user_id: Uuid, session_id: Uuid, minutes: i64) -> Result<bool, sqlx::Error> {
+        sqlx::query_scalar(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM sessions
+                WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL
+                  AND created_at > NOW() - make_interval(mins => $3)
+            )
+            "#,
+        )
+        .bind(session_id)
+        .bind(user_id)
+        .bind(minutes as i32)
+        .fetch_one(pool)
+        .await
+    }
+
+    pub async fn list_active(pool: &PgPool, user_id: Uuid) -> Result<Vec<Session>, sqlx::Error> {
+        sqlx::query_as::<_, Session>(
+            r#"
+            SELECT id, user_id, device_name, user_agent, ip_address, created_at, last_used_at, revoked_at
+            FROM sessions
+            WHERE user_id = $1 AND revoked_at IS NULL
+            ORDER BY last_used_at DESC
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Records that the session was used. Writes at most once a minute per
+    /// session so authenticated requests don't each update the row.
+    pub async fn touch(pool: &PgPool, session_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"UPDATE sessions SET last_used_at = NOW() WHERE id = $1 AND last_used_at < NOW() - INTERVAL '1 minute'"#,
+        )
+        .bind(session_id)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn revoke(pool: &PgPool, user_id: Uuid, session_id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"UPDATE sessions SET revoked_at = NOW() WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL"#,
+        )
+        .bind(session_id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Revokes every active session of the user, optionally sparing one
+    /// (typically the session making the request).
+    pub async fn revoke_all(pool: &PgPool, user_id: Uuid, except: Option<Uuid>) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            UPDATE sessions
+            SET revoked_at = NOW()
+            WHERE user_id = $1 AND revoked_at IS NULL AND ($2::UUID IS NULL OR id <> $2)
+            "#,
+        )
+        .bind(user_id)
+        .bind(except)
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+}
@@ -1,9 +1,9 @@
 use serde::{Deserialize, Serialize};
-use sqlx::FromRow;
+use sqlx::{FromRow, PgPool};
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
 
-#[derive(Debug, Serialize, Deserialize, FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct Split {
     pub id: Uuid,
     pub expense_id: Uuid,
@@ -26,6 +26,78 @@ pub struct SplitResponse {
     pub created_at: DateTime<Utc>,
 }
 
+/// One line of a user's balance summary: either a group (`is_group`, with
+/// `id` NULL for direct expenses) or a person, netted over unsettled splits.
+#[derive(Debug, FromRow)]
+pub struct BalanceEntry {
+    pub is_group: bool,
+    pub id: Option<Uuid>,
+    pub name: Option<String>,
+    pub net_balance: f64, // Positive when the caller is owed
+}
+
+impl Split {
+    /// What `other_id` owes `user_id` across all unsettled splits; negative
+    /// when `user_id` is the one who owes.
+    pub async fn net_balance_between(pool: &PgPool, user_id: Uuid, other_id: Uuid) -> Result<f64, sqlx::Error> {
+        sqlx::query_scalar(
+            r#"
+            SELECT COALESCE(SUM(
+                CASE WHEN e.paid_by = $1 THEN s.amount ELSE -s.amount END
+            ), 0)::FLOAT8
+            FROM splits s
+            JOIN expenses e ON e.id = s.expense_id
+            WHERE s.is_settled = FALSE
+              AND e.deleted_at IS NULL
+              AND ((e.paid_by = $1 AND s.user_id = $2) OR (e.paid_by = $2 AND s.user_id = $1))
+            "#,
+        )
+        .bind(user_id)
+        .bind(other_id)
+        .fetch_one(pool)
+        .await
+    }
+
+    /// Net balances of `user_id` per group and per person, in one pass over
+    /// their unsettled splits.
+    pub async fn balances_for(pool: &PgPool, user_id: Uuid) -> Result<Vec<BalanceEntry>, sqlx::Error> {
+        sqlx::query_as::<_, BalanceEntry>(
+            r#"
+            WITH entries AS (
+                SELECT e.group_id,
+                       CASE WHEN e.paid_by = $1 THEN s.user_id ELSE e.paid_by END AS other_id,
+                       CASE WHEN e.paid_by = $1 THEN s.amount ELSE -s.amount END AS amount
+                FROM splits s
+                JOIN expenses e ON e.id = s.expense_id
+                WHERE s.is_settled = FALSE
+                  AND e.deleted_at IS NULL
+                  AND s.user_id <> e.paid_by
+                  AND (e.paid_by = $1 OR s.user_id = $1)
+            ),
+            totals AS (
+                SELECT GROUPING(other_id) = 1 AS is_group,
+                       group_id, other_id,
+                       SUM(amount) AS net_balance
+                FROM entries
+                GROUP BY GROUPING SETS ((group_id), (other_id))
+                HAVING ABS(SUM(amount)) >= 0.005
+            )
+            SELECT t.is_group,
+                   CASE WHEN t.is_group THEN t.group_id ELSE t.other_id END AS id,
+                   CASE WHEN t.is_group THEN g.name ELSE u.name END AS name,
+                   t.net_balance::FLOAT8 AS net_balance
+            FROM totals t
+            LEFT JOIN groups g ON t.is_group AND g.id = t.group_id
+            LEFT JOIN users u ON NOT t.is_group AND u.id = t.other_id
+            ORDER BY t.net_balance DESC
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+    }
+}
+
 impl From<Split> for SplitResponse {
     fn from(split: Split) -> Self {
         SplitResponse {
@@ -0,0 +1,172 @@
+use data_encoding::BASE32_NOPAD;
+use rand::RngCore;
+use sqlx::{FromRow, PgPool};
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use crate::utils::auth::hash_token;
+use crate::utils::totp::{generate_secret, verify_code};
+
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+#[derive(Debug, FromRow)]
+pub struct TwoFactor {
+    pub user_id: Uuid,
+    pub secret: String,
+    pub enabled_at: Option<DateTime<Utc>>,
+    pub last_used_step: Option<i64>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl TwoFactor {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled_at.is_some()
+    }
+
+    pub async fn find(pool: &PgPool, user_id: Uuid) -> Result<Option<TwoFactor>, sqlx::Error> {
+        sqlx::query_as::<_, TwoFactor>(
+            r#"SELECT user_id, secret, enabled_at, last_used_step, created_at, updated_at FROM user_two_factor WHERE user_id = $1"#,
+        )
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+    }
+
+    pub async fn is_enabled_for(pool: &PgPool, user_id: Uuid) -> Result<bool, sqlx::Error> {
+        Ok(Self::find(pool, user_id).await?.is_some_and(|tf| tf.is_enabled()))
+    }
+
+    /// Starts (or restarts) enrollment with a fresh secret. The secret is not
+    /// enforced at login until `enable` confirms the user can produce codes.
+    pub async fn begin_enrollment(pool: &PgPool, user_id: Uuid) -> Result<String, sqlx::Error> {
+        let secret = generate_secret();
+        sqlx::query(
+            r#"
+            INSERT INTO user_two_factor (user_id, secret)
+            VALUES ($1, $2)
+            ON CONFLICT (user_id) DO UPDATE
+            SET secret = EXCLUDED.secret, enabled_at = NULL, last_used_step = NULL, updated_at = NOW()
+            "#,
+        )
+        .bind(user_id)
+        .bind(&secret)
+        .execute(pool)
+        .await?;
+        Ok(secret)
+    }
+
+    /// Verifies a TOTP code and records its time step so it can't be reused.
+    pub async fn check_code(&self, pool: &PgPool, code: &str) -> Result<bool, sqlx::Error> {
+        let step = match verify_code(&self.secret, code, Utc::now().timestamp()) {
+            Some(step) => step,
+            None => return Ok(false),
+        };
+        let result = sqlx::query(
+            r#"
+            UPDATE user_two_factor
+            SET last_used_step = $1, updated_at = NOW()
+            WHERE user_id = $2 AND (last_used_step IS NULL OR last_used_step < $1)
+            "#,
+        )
+        .bind(step)
+        .bind(self.user_id)
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Accepts either a current TOTP code or one of the user's unused recovery codes.
+    pub async fn verify_second_factor(
+        &self,
+        pool: &PgPool,
+        code: Option<&str>,
+        recovery_code: Option<&str>,
+    ) -> Result<bool, sqlx::Error> {
+        match (code, recovery_code) {
+            (Some(code), _) => self.check_code(pool, code).await,
+            (None, Some(recovery_code)) => RecoveryCode::consume(pool, self.user_id, recovery_code).await,
+            (None, None) => Ok(false),
+        }
+    }
+
+    pub async fn enable(pool: &PgPool, user_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"UPDATE user_two_factor SET enabled_at = NOW(), updated_at = NOW() WHERE user_id = $1"#,
+        )
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn disable(pool: &PgPool, user_id: Uuid) -> Result<(), sqlx::Error> {
+        let mut tx = pool.begin().await?;
+        sqlx::query(r#"DELETE FROM user_two_factor WHERE user_id = $1"#)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(r#"DELETE FROM recovery_codes WHERE user_id = $1"#)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await
+    }
+}
+
+pub struct RecoveryCode;
+
+impl RecoveryCode {
+    fn generate() -> String {
+        let mut bytes = [0u8; 10];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let encoded = BASE32_NOPAD.encode(&bytes);
+        encoded
+            .as_bytes()
+            .chunks(4)
+            .map(|chunk| String::from_utf8_lossy(chunk).into_owned())
+            .collect::<Vec<_>>()
+            .join("-")
+    }
+
+    /// Codes are compared case-insensitively and without separators, since
+    /// users tend to retype them by hand.
+    fn normalize(code: &str) -> String {
+        code.chars()
+            .filter(|c| c.is_ascii_alphanumeric())
+            .map(|c| c.to_ascii_uppercase())
+            .collect()
+    }
+
+    /// Replaces any existing recovery codes and returns the new plaintext
+    /// codes. Only hashes are stored, so this is the one chance to show them.
+    pub async fn regenerate(pool: &PgPool, user_id: Uuid) -> Result<Vec<String>, sqlx::Error> {
+        let codes: Vec<String> = (0..RECOVERY_CODE_COUNT).map(|_| Self::generate()).collect();
+
+        let mut tx = pool.begin().await?;
+        sqlx::query(r#"DELETE FROM recovery_codes WHERE user_id = $1"#)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        for code in &codes {
+            sqlx::query(r#"INSERT INTO recovery_codes (user_id, code_hash) VALUES ($1, $2)"#)
+                .bind(user_id)
+                .bind(hash_token(&Self::normalize(code)))
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+
+        Ok(codes)
+    }
+
+    pub async fn consume(pool: &PgPool, user_id: Uuid, code: &str) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"UPDATE recovery_codes SET used_at = NOW() WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL"#,
+        )
+        .bind(user_id)
+        .bind(hash_token(&Self::normalize(code)))
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
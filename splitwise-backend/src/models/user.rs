@@ -2,6 +2,9 @@ use serde::{Deserialize, Serialize};
 use sqlx::{FromRow, PgPool};
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
+use crate::utils::auth::verify_password;
+use crate::utils::helpers::escape_like;
+use crate::utils::images::avatar_url;
 
 #[derive(Debug, Serialize, Deserialize, FromRow)]
 pub struct User {
@@ -9,7 +12,12 @@ pub struct User {
     pub name: String,
     pub email: String,
     #[serde(skip_serializing)]
-    pub password_hash: String,
+    pub password_hash: Option<String>, // None for accounts that only sign in through OIDC
+    pub email_verified_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing)]
+    pub token_version: i32,
+    #[serde(skip_serializing)]
+    pub avatar_key: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -26,21 +34,53 @@ pub struct UserResponse {
     pub id: Uuid,
     pub email: String,
     pub name: String,
+    pub email_verified: bool,
+    pub has_password: bool,
+    pub avatar_url: Option<String>,
     pub created_at: DateTime<Utc>,
 }
 
+/// A user directory entry. `email` is only filled in for people who share a
+/// group with the searcher.
+#[derive(Debug, Serialize, FromRow)]
+pub struct UserSearchResult {
+    pub id: Uuid,
+    pub name: String,
+    pub email: Option<String>,
+    pub shares_group: bool,
+}
+
 impl From<User> for UserResponse {
     fn from(user: User) -> Self {
         UserResponse {
             id: user.id,
             email: user.email,
             name: user.name,
+            email_verified: user.email_verified_at.is_some(),
+            has_password: user.password_hash.is_some(),
+            avatar_url: avatar_url("users", user.id, user.avatar_key.as_deref()),
             created_at: user.created_at,
         }
     }
 }
 
 impl User {
+    pub fn is_email_verified(&self) -> bool {
+        self.email_verified_at.is_some()
+    }
+
+    pub fn has_password(&self) -> bool {
+        self.password_hash.is_some()
+    }
+
+    /// Always false for passwordless accounts.
+    pub fn check_password(&self, password: &str) -> bool {
+        match &self.password_hash {
+            Some(hash) => verify_password(password, hash).unwrap_or(false),
+            None => false,
+        }
+    }
+
     pub fn to_response(self) -> UserResponse {
         self.into()
     }
@@ -50,7 +90,7 @@ impl User {
             r#"
             INSERT INTO users (name, email, password_hash)
             VALUES ($1, $2, $3)
-            RETURNING id, name, email, password_hash, created_at, updated_at
+            RETURNING id, name, email, password_hash, email_verified_at, token_version, avatar_key, created_at, updated_at
             "#,
         )
         .bind(&new_user.name)
@@ -61,10 +101,26 @@ impl User {
         Ok(user)
     }
 
+    /// Creates an account for someone signing in through an identity provider
+    /// that has already verified their email address.
+    pub async fn create_passwordless(pool: &PgPool, name: &str, email: &str) -> Result<User, sqlx::Error> {
+        sqlx::query_as::<_, User>(
+            r#"
+            INSERT INTO users (name, email, email_verified_at)
+            VALUES ($1, $2, NOW())
+            RETURNING id, name, email, password_hash, email_verified_at, token_version, avatar_key, created_at, updated_at
+            "#,
+        )
+        .bind(name)
+        .bind(email)
+        .fetch_one(pool)
+        .await
+    }
+
     pub async fn find_by_email(pool: &PgPool, email: &str) -> Result<Option<User>, sqlx::Error> {
         let user = sqlx::query_as::<_, User>(
             r#"
-            SELECT id, name, email, password_hash, created_at, updated_at
+            SELECT id, name, email, password_hash, email_verified_at, token_version, avatar_key, created_at, updated_at
             FROM users
             WHERE email = $1
             "#,
@@ -74,4 +130,192 @@ impl User {
         .await?;
         Ok(user)
     }
-} 
\ No newline at end of file
+
+    pub async fn find_by_id(pool: &PgPool, user_id: Uuid) -> Result<Option<User>, sqlx::Error> {
+        let user = sqlx::query_as::<_, User>(
+            r#"
+            SELECT id, name, email, password_hash, email_verified_at, token_version, avatar_key, created_at, updated_at
+            FROM users
+            WHERE id = $1
+            "#,
+        )
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+        Ok(user)
+    }
+
+    /// Marks `email` as verified for the user, switching the account over to
+    /// it first if it's a pending address change.
+    pub async fn confirm_email(pool: &PgPool, user_id: Uuid, email: &str) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            UPDATE users
+            SET email = $2, email_verified_at = NOW(), updated_at = NOW()
+            WHERE id = $1
+            "#,
+        )
+        .bind(user_id)
+        .bind(email)
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn update_name(pool: &PgPool, user_id: Uuid, name: &str) -> Result<User, sqlx::Error> {
+        let user = sqlx::query_as::<_, User>(
+            r#"
+            UPDATE users
+            SET name = $2, updated_at = NOW()
+            WHERE id = $1
+            RETURNING id, name, email, password_hash, email_verified_at, token_version, avatar_key, created_at, updated_at
+            "#,
+        )
+        .bind(user_id)
+        .bind(name)
+        .fetch_one(pool)
+        .await?;
+        Ok(user)
+    }
+
+    /// Stores a new password hash and bumps `token_version`, which revokes
+    /// every token issued so far. Returns the new version.
+    pub async fn update_password(pool: &PgPool, user_id: Uuid, password_hash: &str) -> Result<i32, sqlx::Error> {
+        let token_version = sqlx::query_scalar(
+            r#"
+            UPDATE users
+            SET password_hash = $2, token_version = token_version + 1, updated_at = NOW()
+            WHERE id = $1
+            RETURNING token_version
+            "#,
+        )
+        .bind(user_id)
+        .bind(password_hash)
+        .fetch_one(pool)
+        .await?;
+        Ok(token_version)
+    }
+
+    /// Directory search: prefix match on name, or exact match on a verified
+    /// email. People sharing a group with `searcher_id` are listed first.
+    pub async fn search(
+        pool: &PgPool,
+        searcher_id: Uuid,
+        query: &str,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<UserSearchResult>, sqlx::Error> {
+        sqlx::query_as::<_, UserSearchResult>(
+            r#"
+            SELECT id, name,
+                   CASE WHEN shares_group THEN email END AS email,
+                   shares_group
+            FROM (
+                SELECT u.id, u.name, u.email,
+                       EXISTS (
+                           SELECT 1
+                           FROM group_members mine
+                           JOIN group_members theirs ON theirs.group_id = mine.group_id
+                           WHERE mine.user_id = $1 AND theirs.user_id = u.id
+                       ) AS shares_group
+                FROM users u
+                WHERE u.id <> $1
+                  AND u.deleted_at IS NULL
+                  AND (
+                      LOWER(u.name) LIKE $2 ESCAPE '\'
+                      OR (u.email_verified_at IS NOT NULL AND LOWER(u.email) = $3)
+                  )
+            ) matches
+            ORDER BY shares_group DESC, LOWER(name), id
+            LIMIT $4 OFFSET $5
+            "#,
+        )
+        .bind(searcher_id)
+        .bind(format!("{}%", escape_like(&query.to_lowercase())))
+        .bind(query.to_lowercase())
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Points the user at a new avatar (or none) and returns the previous key
+    /// so its blobs can be cleaned up.
+    pub async fn set_avatar_key(pool: &PgPool, user_id: Uuid, avatar_key: Option<&str>) -> Result<Option<String>, sqlx::Error> {
+        sqlx::query_scalar(
+            r#"
+            UPDATE users SET avatar_key = $2, updated_at = NOW()
+            FROM (SELECT avatar_key AS previous FROM users WHERE id = $1 FOR UPDATE) old
+            WHERE id = $1
+            RETURNING old.previous
+            "#,
+        )
+        .bind(user_id)
+        .bind(avatar_key)
+        .fetch_one(pool)
+        .await
+    }
+
+    /// Invalidates every token issued so far for the user.
+    pub async fn revoke_tokens(pool: &PgPool, user_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"UPDATE users SET token_version = token_version + 1, updated_at = NOW() WHERE id = $1"#,
+        )
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Scrubs personal data from an account while keeping the row, so group
+    /// history (expenses paid, splits owed) stays intact and attributed to
+    /// "Deleted user". Credentials, 2FA state, memberships and login history
+    /// are removed, all outstanding tokens are revoked and the recurring
+    /// expenses they set up are paused.
+    pub async fn anonymize(pool: &PgPool, user_id: Uuid) -> Result<(), sqlx::Error> {
+        let mut tx = pool.begin().await?;
+        // Login attempts are keyed on the email, so clear them before it goes
+        sqlx::query(
+            r#"
+            DELETE FROM login_attempts
+            WHERE user_id = $1 OR email = (SELECT LOWER(TRIM(email)) FROM users WHERE id = $1)
+            "#,
+        )
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(
+            r#"
+            UPDATE users
+            SET name = 'Deleted user',
+                email = 'deleted-' || id || '@deleted.invalid',
+                password_hash = NULL,
+                email_verified_at = NULL,
+                token_version = token_version + 1,
+                avatar_key = NULL,
+                deleted_at = NOW(),
+                updated_at = NOW()
+            WHERE id = $1
+            "#,
+        )
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+        for statement in [
+            r#"DELETE FROM group_members WHERE user_id = $1"#,
+            r#"DELETE FROM email_verification_tokens WHERE user_id = $1"#,
+            r#"DELETE FROM user_two_factor WHERE user_id = $1"#,
+            r#"DELETE FROM recovery_codes WHERE user_id = $1"#,
+            r#"DELETE FROM sessions WHERE user_id = $1"#,
+            r#"DELETE FROM api_tokens WHERE user_id = $1"#,
+            r#"DELETE FROM user_identities WHERE user_id = $1"#,
+            r#"DELETE FROM user_preferences WHERE user_id = $1"#,
+            r#"DELETE FROM friendships WHERE requester_id = $1 OR addressee_id = $1"#,
+            // Nobody would be left to manage their schedules
+            r#"UPDATE recurring_expenses SET is_paused = TRUE, updated_at = NOW() WHERE created_by = $1"#,
+        ] {
+            sqlx::query(statement).bind(user_id).execute(&mut *tx).await?;
+        }
+        tx.commit().await
+    }
+}
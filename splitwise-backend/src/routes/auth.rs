@@ -1,11 +1,23 @@
 use actix_web::web;
-use crate::handlers::auth_handler::{login, register, logout};
+use crate::handlers::auth_handler::{login, login_two_factor, register, refresh, logout, verify_email, resend_verification};
+use crate::handlers::oidc_handler::{authorize, callback};
+use crate::handlers::two_factor_handler::{enroll, confirm, disable, regenerate_recovery_codes};
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/auth")
             .route("/register", web::post().to(register))
             .route("/login", web::post().to(login))
+            .route("/login/2fa", web::post().to(login_two_factor))
+            .route("/refresh", web::post().to(refresh))
+            .route("/oidc/authorize", web::get().to(authorize))
+            .route("/oidc/callback", web::post().to(callback))
             .route("/logout", web::post().to(logout))
+            .route("/verify-email", web::get().to(verify_email))
+            .route("/verify-email/resend", web::post().to(resend_verification))
+            .route("/2fa/enroll", web::post().to(enroll))
+            .route("/2fa/confirm", web::post().to(confirm))
+            .route("/2fa/disable", web::post().to(disable))
+            .route("/2fa/recovery-codes", web::post().to(regenerate_recovery_codes))
     );
 } 
\ No newline at end of file
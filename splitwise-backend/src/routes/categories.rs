@@ -0,0 +1,9 @@
+use actix_web::web;
+use crate::handlers::category_handler::list_categories;
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/categories")
+            .route("", web::get().to(list_categories))
+    );
+}
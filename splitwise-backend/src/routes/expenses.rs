@@ -1,6 +1,12 @@
 use actix_web::web;
+use crate::handlers::attachment_handler::{
+    list_attachments, upload_attachments, get_attachment, delete_attachment, MAX_ATTACHMENT_BODY_BYTES,
+};
+use crate::handlers::category_handler::suggest_category;
+use crate::handlers::comment_handler::{list_comments, create_comment, update_comment, delete_comment};
 use crate::handlers::expense_handler::{
     create_expense, get_expenses, get_expense_by_id, update_expense, delete_expense, get_splits_for_expense,
+    get_expense_history,
 };
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
@@ -8,9 +14,23 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
         web::scope("/expenses")
             .route("", web::post().to(create_expense))
             .route("", web::get().to(get_expenses))
+            .route("/suggest-category", web::get().to(suggest_category))
             .route("/{expense_id}", web::get().to(get_expense_by_id))
             .route("/{expense_id}", web::put().to(update_expense))
             .route("/{expense_id}", web::delete().to(delete_expense))
             .route("/{expense_id}/splits", web::get().to(get_splits_for_expense))
+            .route("/{expense_id}/history", web::get().to(get_expense_history))
+            .route("/{expense_id}/comments", web::get().to(list_comments))
+            .route("/{expense_id}/comments", web::post().to(create_comment))
+            .route("/{expense_id}/comments/{comment_id}", web::put().to(update_comment))
+            .route("/{expense_id}/comments/{comment_id}", web::delete().to(delete_comment))
+            .service(
+                web::resource("/{expense_id}/attachments")
+                    .app_data(web::PayloadConfig::new(MAX_ATTACHMENT_BODY_BYTES))
+                    .route(web::get().to(list_attachments))
+                    .route(web::post().to(upload_attachments)),
+            )
+            .route("/{expense_id}/attachments/{attachment_id}", web::get().to(get_attachment))
+            .route("/{expense_id}/attachments/{attachment_id}", web::delete().to(delete_attachment))
     );
 } 
\ No newline at end of file
@@ -0,0 +1,17 @@
+use actix_web::web;
+use crate::handlers::friend_handler::{
+    get_friends, get_friend_requests, send_friend_request, accept_friend_request, delete_friend_request,
+    remove_friend,
+};
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/friends")
+            .route("", web::get().to(get_friends))
+            .route("/requests", web::get().to(get_friend_requests))
+            .route("/requests", web::post().to(send_friend_request))
+            .route("/requests/{request_id}/accept", web::post().to(accept_friend_request))
+            .route("/requests/{request_id}", web::delete().to(delete_friend_request))
+            .route("/{user_id}", web::delete().to(remove_friend))
+    );
+}
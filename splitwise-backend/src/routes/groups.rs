@@ -1,4 +1,10 @@
 use actix_web::web;
+use crate::handlers::avatar_handler::{
+    delete_group_avatar, get_group_avatar, upload_group_avatar, MAX_UPLOAD_BODY_BYTES,
+};
+use crate::handlers::category_handler::{
+    create_group_category, delete_group_category, list_category_rules, create_category_rule, delete_category_rule,
+};
 use crate::handlers::group_handler::{
     create_group, get_groups, get_group_by_id, update_group, delete_group, add_group_member, remove_group_member,
 };
@@ -11,6 +17,24 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
             .route("/{group_id}", web::get().to(get_group_by_id))
             .route("/{group_id}", web::put().to(update_group))
             .route("/{group_id}", web::delete().to(delete_group))
+            .service(
+                web::resource("/{group_id}/avatar")
+                    .app_data(web::PayloadConfig::new(MAX_UPLOAD_BODY_BYTES))
+                    .route(web::get().to(get_group_avatar))
+                    .route(web::put().to(upload_group_avatar))
+                    .route(web::delete().to(delete_group_avatar)),
+            )
+            .route("/{group_id}/categories", web::post().to(create_group_category))
+            .route(
+                "/{group_id}/categories/{category_id}",
+                web::delete().to(delete_group_category),
+            )
+            .route("/{group_id}/category-rules", web::get().to(list_category_rules))
+            .route("/{group_id}/category-rules", web::post().to(create_category_rule))
+            .route(
+                "/{group_id}/category-rules/{rule_id}",
+                web::delete().to(delete_category_rule),
+            )
             .route("/{group_id}/members", web::post().to(add_group_member))
             .route(
                 "/{group_id}/members/{user_id}",
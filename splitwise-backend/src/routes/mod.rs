@@ -2,15 +2,25 @@ pub mod auth;
 pub mod groups;
 pub mod expenses;
 pub mod users;
+pub mod friends;
+pub mod categories;
+pub mod recurring_expenses;
+pub mod search;
 
 use actix_web::web;
+use crate::handlers::auth_handler::jwks;
 
 pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/.well-known/jwks.json", web::get().to(jwks));
     cfg.service(
         web::scope("/api")
             .configure(auth::configure)
             .configure(users::configure)
             .configure(groups::configure)
             .configure(expenses::configure)
+            .configure(friends::configure)
+            .configure(categories::configure)
+            .configure(recurring_expenses::configure)
+            .configure(search::configure)
     );
 } 
\ No newline at end of file
@@ -0,0 +1,19 @@
+use actix_web::web;
+use crate::handlers::recurring_expense_handler::{
+    create_recurring_expense, get_recurring_expenses, get_recurring_expense, update_recurring_expense,
+    delete_recurring_expense, pause_recurring_expense, resume_recurring_expense, skip_recurring_occurrence,
+};
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/recurring-expenses")
+            .route("", web::post().to(create_recurring_expense))
+            .route("", web::get().to(get_recurring_expenses))
+            .route("/{recurring_id}", web::get().to(get_recurring_expense))
+            .route("/{recurring_id}", web::put().to(update_recurring_expense))
+            .route("/{recurring_id}", web::delete().to(delete_recurring_expense))
+            .route("/{recurring_id}/pause", web::post().to(pause_recurring_expense))
+            .route("/{recurring_id}/resume", web::post().to(resume_recurring_expense))
+            .route("/{recurring_id}/skip", web::post().to(skip_recurring_occurrence))
+    );
+}
@@ -0,0 +1,9 @@
+use actix_web::web;
+use crate::handlers::search_handler::search_all;
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/search")
+            .route("", web::get().to(search_all))
+    );
+}
\ No newline at end of file
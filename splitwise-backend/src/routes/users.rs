@@ -1,10 +1,42 @@
 use actix_web::web;
-use crate::handlers::user_handler::{get_current_user, get_user_profile};
+use crate::handlers::avatar_handler::{
+    delete_user_avatar, get_user_avatar, upload_user_avatar, MAX_UPLOAD_BODY_BYTES,
+};
+use crate::handlers::api_token_handler::{create_api_token, list_api_tokens, revoke_api_token};
+use crate::handlers::preferences_handler::{get_preferences, update_preferences};
+use crate::handlers::report_handler::{get_balance_summary, get_spending_report};
+use crate::handlers::session_handler::{list_sessions, revoke_session, revoke_all_sessions};
+use crate::handlers::user_handler::{
+    get_current_user, update_current_user, delete_current_user, export_current_user, change_password,
+    get_user_profile, search_users,
+};
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/users")
+            .route("/search", web::get().to(search_users))
             .route("/me", web::get().to(get_current_user))
+            .route("/me", web::put().to(update_current_user))
+            .route("/me", web::delete().to(delete_current_user))
+            .service(
+                web::resource("/me/avatar")
+                    .app_data(web::PayloadConfig::new(MAX_UPLOAD_BODY_BYTES))
+                    .route(web::put().to(upload_user_avatar))
+                    .route(web::delete().to(delete_user_avatar)),
+            )
+            .route("/me/export", web::get().to(export_current_user))
+            .route("/me/password", web::post().to(change_password))
+            .route("/me/preferences", web::get().to(get_preferences))
+            .route("/me/preferences", web::put().to(update_preferences))
+            .route("/me/report", web::get().to(get_spending_report))
+            .route("/me/sessions", web::get().to(list_sessions))
+            .route("/me/sessions", web::delete().to(revoke_all_sessions))
+            .route("/me/sessions/{session_id}", web::delete().to(revoke_session))
+            .route("/me/summary", web::get().to(get_balance_summary))
+            .route("/me/tokens", web::post().to(create_api_token))
+            .route("/me/tokens", web::get().to(list_api_tokens))
+            .route("/me/tokens/{token_id}", web::delete().to(revoke_api_token))
             .route("/{user_id}/profile", web::get().to(get_user_profile))
+            .route("/{user_id}/avatar", web::get().to(get_user_avatar))
     );
 } 
\ No newline at end of file
@@ -1,16 +1,43 @@
+use actix_web::HttpRequest;
 use bcrypt::{hash, verify, BcryptError};
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use jsonwebtoken::encode;
+use rand::RngCore;
 use serde::{Deserialize, Serialize};
-use std::env;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
 use uuid::Uuid;
+use crate::models::api_token::{ApiToken, Scope, API_TOKEN_PREFIX};
+use crate::models::session::Session;
+use crate::models::two_factor::TwoFactor;
+use crate::models::user::User;
+use crate::utils::error::AppError;
+use crate::utils::jwt_keys::keys;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String, // user_id
     pub exp: usize,  // expiration time
     pub iat: usize,  // issued at
+    #[serde(default)]
+    pub typ: TokenType,
+    #[serde(default)]
+    pub ver: i32, // users.token_version at issue time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<Uuid>, // sessions.id the token belongs to
 }
 
+/// Distinguishes API access tokens from the short-lived token handed out
+/// between the password and the second-factor step of a login.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenType {
+    #[default]
+    Access,
+    MfaChallenge,
+}
+
+pub const MFA_CHALLENGE_TTL_MINUTES: i64 = 5;
+
 pub fn hash_password(password: &str) -> Result<String, BcryptError> {
     hash(password, 12)
 }
@@ -19,10 +46,15 @@ pub fn verify_password(password: &str, hash: &str) -> Result<bool, BcryptError>
     verify(password, hash)
 }
 
-pub fn create_jwt(user_id: Uuid) -> Result<String, jsonwebtoken::errors::Error> {
-    let secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
+fn encode_claims(
+    user_id: Uuid,
+    token_version: i32,
+    session_id: Option<Uuid>,
+    typ: TokenType,
+    ttl: chrono::Duration,
+) -> Result<String, jsonwebtoken::errors::Error> {
     let expiration = chrono::Utc::now()
-        .checked_add_signed(chrono::Duration::hours(24))
+        .checked_add_signed(ttl)
         .expect("valid timestamp")
         .timestamp() as usize;
 
@@ -30,29 +62,172 @@ pub fn create_jwt(user_id: Uuid) -> Result<String, jsonwebtoken::errors::Error>
         sub: user_id.to_string(),
         exp: expiration,
         iat: chrono::Utc::now().timestamp() as usize,
+        typ,
+        ver: token_version,
+        sid: session_id,
     };
 
-    encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(secret.as_ref()),
-    )
+    let keys = keys();
+    encode(&keys.header(), &claims, keys.signing_key())
+}
+
+fn decode_claims(token: &str, expected: TokenType) -> Result<Claims, jsonwebtoken::errors::Error> {
+    let claims: Claims = keys().decode(token)?;
+    if claims.typ != expected {
+        return Err(jsonwebtoken::errors::ErrorKind::InvalidToken.into());
+    }
+    Ok(claims)
+}
+
+pub fn create_jwt(user_id: Uuid, token_version: i32, session_id: Option<Uuid>) -> Result<String, jsonwebtoken::errors::Error> {
+    encode_claims(user_id, token_version, session_id, TokenType::Access, chrono::Duration::hours(24))
 }
 
 pub fn verify_jwt(token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
-    let secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
-    let token_data = decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(secret.as_ref()),
-        &Validation::default(),
-    )?;
-    Ok(token_data.claims)
+    decode_claims(token, TokenType::Access)
+}
+
+pub fn create_mfa_challenge(user_id: Uuid) -> Result<String, jsonwebtoken::errors::Error> {
+    encode_claims(user_id, 0, None, TokenType::MfaChallenge, chrono::Duration::minutes(MFA_CHALLENGE_TTL_MINUTES))
+}
+
+pub fn verify_mfa_challenge(token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
+    decode_claims(token, TokenType::MfaChallenge)
 }
 
 pub fn extract_token_from_header(auth_header: &str) -> Option<String> {
-    if auth_header.starts_with("Bearer ") {
-        Some(auth_header[7..].to_string())
-    } else {
-        None
+    auth_header.strip_prefix("Bearer ").map(|token| token.to_string())
+}
+
+/// Who a request is authenticated as. `session_id` is `None` for tokens
+/// issued before sessions were recorded.
+#[derive(Debug, Clone, Copy)]
+pub struct AuthContext {
+    pub user_id: Uuid,
+    pub session_id: Option<Uuid>,
+}
+
+/// Resolves the bearer token on a request to a user id. Besides checking the
+/// signature and expiry, this rejects tokens issued before the user's
+/// `token_version` was last bumped (e.g. by a password change) and tokens
+/// whose session has been revoked. Personal access tokens are refused; use
+/// `authorize` for endpoints they may call.
+pub async fn authenticate(req: &HttpRequest, pool: &PgPool) -> Result<Uuid, AppError> {
+    Ok(authenticate_context(req, pool).await?.user_id)
+}
+
+fn bearer_token(req: &HttpRequest) -> Result<String, AppError> {
+    req.headers()
+        .get("Authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(extract_token_from_header)
+        .ok_or_else(|| AppError::unauthorized("Missing token"))
+}
+
+/// Like `authenticate`, but only for session tokens. Account management
+/// (passwords, 2FA, sessions, API tokens) goes through this so a leaked
+/// personal access token can't be used to take over the account.
+pub async fn authenticate_context(req: &HttpRequest, pool: &PgPool) -> Result<AuthContext, AppError> {
+    let token = bearer_token(req)?;
+    if token.starts_with(API_TOKEN_PREFIX) {
+        return Err(AppError::forbidden("This endpoint can't be used with an API token"));
+    }
+    let claims = verify_jwt(&token)?;
+    let user_id = Uuid::parse_str(&claims.sub)
+        .map_err(|_| AppError::unauthorized("Invalid user id in token"))?;
+
+    let token_version: Option<i32> = sqlx::query_scalar(r#"SELECT token_version FROM users WHERE id = $1"#)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+    if token_version != Some(claims.ver) {
+        return Err(AppError::unauthorized("Token has been revoked"));
     }
-} 
\ No newline at end of file
+
+    if let Some(session_id) = claims.sid {
+        if !Session::is_active(pool, user_id, session_id).await? {
+            return Err(AppError::unauthorized("Token has been revoked"));
+        }
+        Session::touch(pool, session_id).await?;
+    }
+
+    Ok(AuthContext { user_id, session_id: claims.sid })
+}
+
+/// How recently a passwordless account must have signed in to confirm a
+/// sensitive change without a two-factor code.
+pub const REAUTH_WINDOW_MINUTES: i64 = 10;
+
+/// Re-authentication for sensitive account changes. Accounts with a password
+/// must enter it. Passwordless accounts have nothing to re-enter, so the
+/// session must have signed in within `REAUTH_WINDOW_MINUTES`, or, with
+/// two-factor authentication on, a current code must be given.
+pub async fn confirm_identity(
+    pool: &PgPool,
+    user: &User,
+    auth: AuthContext,
+    password: Option<&str>,
+    code: Option<&str>,
+) -> Result<(), AppError> {
+    if user.has_password() {
+        if !password.is_some_and(|p| user.check_password(p)) {
+            return Err(AppError::unauthorized("Password is incorrect"));
+        }
+        return Ok(());
+    }
+    if let Some(session_id) = auth.session_id {
+        if Session::signed_in_within(pool, user.id, session_id, REAUTH_WINDOW_MINUTES).await? {
+            return Ok(());
+        }
+    }
+    if let (Some(code), Some(two_factor)) = (code, TwoFactor::find(pool, user.id).await?) {
+        if two_factor.is_enabled() && two_factor.check_code(pool, code).await? {
+            return Ok(());
+        }
+    }
+    Err(AppError::unauthorized("Sign in again to confirm this change"))
+}
+
+/// Authenticates a request that may use either a session token or a
+/// personal access token. Session tokens may do anything; API tokens must
+/// carry `scope`.
+pub async fn authorize(req: &HttpRequest, pool: &PgPool, scope: Scope) -> Result<Uuid, AppError> {
+    let token = bearer_token(req)?;
+    if !token.starts_with(API_TOKEN_PREFIX) {
+        return Ok(authenticate_context(req, pool).await?.user_id);
+    }
+
+    let api_token = ApiToken::find_active(pool, &token)
+        .await?
+        .ok_or_else(|| AppError::unauthorized("Invalid token"))?;
+    if !api_token.scopes().contains(&scope) {
+        return Err(AppError::forbidden(&format!("API token is missing the {} scope", scope.as_str())));
+    }
+    ApiToken::touch(pool, api_token.id).await?;
+    Ok(api_token.user_id)
+}
+
+/// Who a session or personal access token belongs to, whatever its scopes.
+/// For bookkeeping that runs before the handler checks what the token may do.
+pub async fn identify(req: &HttpRequest, pool: &PgPool) -> Result<Uuid, AppError> {
+    let token = bearer_token(req)?;
+    if !token.starts_with(API_TOKEN_PREFIX) {
+        return Ok(authenticate_context(req, pool).await?.user_id);
+    }
+    let api_token = ApiToken::find_active(pool, &token)
+        .await?
+        .ok_or_else(|| AppError::unauthorized("Invalid token"))?;
+    Ok(api_token.user_id)
+}
+
+/// Generates a random URL-safe token for one-time links such as email verification.
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Hashes a one-time token for storage so a database leak doesn't expose usable tokens.
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
@@ -0,0 +1,104 @@
+use async_trait::async_trait;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use actix_web::web;
+use crate::config::Config;
+
+#[derive(Debug)]
+pub struct BlobError(pub String);
+
+impl fmt::Display for BlobError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Storage for uploaded files such as avatars. Keys are `/`-separated paths
+/// chosen by the application, never by the client.
+#[async_trait]
+pub trait BlobStore: Send + Sync {
+    async fn put(&self, key: &str, content_type: &str, data: Vec<u8>) -> Result<(), BlobError>;
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, BlobError>;
+    async fn delete(&self, key: &str) -> Result<(), BlobError>;
+}
+
+/// Builds the store selected by `BLOB_STORE` (`local` or, when compiled
+/// with the `s3` feature, `s3`).
+pub fn from_config(config: &Config) -> Result<Arc<dyn BlobStore>, String> {
+    match config.blob_store.as_str() {
+        "local" => Ok(Arc::new(LocalBlobStore::new(&config.blob_store_path))),
+        #[cfg(feature = "s3")]
+        "s3" => {
+            let s3 = config.s3.clone().ok_or("BLOB_STORE=s3 requires the S3_* settings")?;
+            Ok(Arc::new(crate::utils::s3_blob_store::S3BlobStore::new(s3)?))
+        }
+        #[cfg(not(feature = "s3"))]
+        "s3" => Err("BLOB_STORE=s3 requires building with the s3 feature".to_string()),
+        other => Err(format!("Unknown BLOB_STORE {}", other)),
+    }
+}
+
+/// Keeps blobs as files under a root directory.
+pub struct LocalBlobStore {
+    root: PathBuf,
+}
+
+impl LocalBlobStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        LocalBlobStore { root: root.into() }
+    }
+
+    fn path_for(&self, key: &str) -> Result<PathBuf, BlobError> {
+        // Keys are generated by us, but a stray ".." must never escape the root
+        let relative = Path::new(key);
+        if key.is_empty() || !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+            return Err(BlobError(format!("Invalid blob key {:?}", key)));
+        }
+        Ok(self.root.join(relative))
+    }
+}
+
+async fn blocking<T, F>(f: F) -> Result<T, BlobError>
+where
+    F: FnOnce() -> std::io::Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    web::block(f)
+        .await
+        .map_err(|e| BlobError(e.to_string()))?
+        .map_err(|e| BlobError(e.to_string()))
+}
+
+#[async_trait]
+impl BlobStore for LocalBlobStore {
+    async fn put(&self, key: &str, _content_type: &str, data: Vec<u8>) -> Result<(), BlobError> {
+        let path = self.path_for(key)?;
+        blocking(move || {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(path, data)
+        })
+        .await
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, BlobError> {
+        let path = self.path_for(key)?;
+        blocking(move || match std::fs::read(path) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        })
+        .await
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), BlobError> {
+        let path = self.path_for(key)?;
+        blocking(move || match std::fs::remove_file(path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        })
+        .await
+    }
+}
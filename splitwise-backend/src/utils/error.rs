@@ -24,10 +24,26 @@ impl AppError {
         Self::new(message, "UNAUTHORIZED")
     }
 
+    pub fn forbidden(message: &str) -> Self {
+        Self::new(message, "FORBIDDEN")
+    }
+
     pub fn bad_request(message: &str) -> Self {
         Self::new(message, "BAD_REQUEST")
     }
 
+    pub fn conflict(message: &str) -> Self {
+        Self::new(message, "CONFLICT")
+    }
+
+    pub fn unprocessable_entity(message: &str) -> Self {
+        Self::new(message, "UNPROCESSABLE_ENTITY")
+    }
+
+    pub fn precondition_required(message: &str) -> Self {
+        Self::new(message, "PRECONDITION_REQUIRED")
+    }
+
     pub fn internal_error(message: &str) -> Self {
         Self::new(message, "INTERNAL_ERROR")
     }
@@ -44,7 +60,11 @@ impl ResponseError for AppError {
         match self.error_type.as_str() {
             "NOT_FOUND" => HttpResponse::NotFound().json(self),
             "UNAUTHORIZED" => HttpResponse::Unauthorized().json(self),
+            "FORBIDDEN" => HttpResponse::Forbidden().json(self),
             "BAD_REQUEST" => HttpResponse::BadRequest().json(self),
+            "CONFLICT" => HttpResponse::Conflict().json(self),
+            "UNPROCESSABLE_ENTITY" => HttpResponse::UnprocessableEntity().json(self),
+            "PRECONDITION_REQUIRED" => HttpResponse::PreconditionRequired().json(self),
             "INTERNAL_ERROR" => HttpResponse::InternalServerError().json(self),
             _ => HttpResponse::InternalServerError().json(self),
         }
@@ -0,0 +1,117 @@
+use actix_web::http::header;
+use actix_web::{HttpRequest, HttpResponse};
+use serde::Serialize;
+use crate::utils::error::AppError;
+
+/// The ETag for a row at `version`.
+pub fn etag(version: i32) -> String {
+    format!("\"{}\"", version)
+}
+
+/// The versions an `If-Match` header accepts.
+pub enum IfMatch {
+    Any, // `*`
+    Versions(Vec<i32>),
+}
+
+impl IfMatch {
+    /// Reads `If-Match`, which updates and deletes must send so they can't
+    /// silently overwrite someone else's change.
+    pub fn from_request(req: &HttpRequest) -> Result<IfMatch, AppError> {
+        let value = req
+            .headers()
+            .get(header::IF_MATCH)
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .ok_or_else(|| AppError::precondition_required("If-Match header with the resource's ETag is required"))?;
+        if value == "*" {
+            return Ok(IfMatch::Any);
+        }
+        // Unparseable tags can't match any version, so they are dropped
+        let versions = value
+            .split(',')
+            .filter_map(|tag| {
+                let tag = tag.trim();
+                tag.strip_prefix("W/").unwrap_or(tag).trim_matches('"').parse().ok()
+            })
+            .collect();
+        Ok(IfMatch::Versions(versions))
+    }
+
+    /// The accepted versions for a `version = ANY(...)` condition, or None
+    /// when any version will do.
+    pub fn versions(&self) -> Option<&[i32]> {
+        match self {
+            IfMatch::Any => None,
+            IfMatch::Versions(versions) => Some(versions),
+        }
+    }
+
+    pub fn matches(&self, version: i32) -> bool {
+        match self {
+            IfMatch::Any => true,
+            IfMatch::Versions(versions) => versions.contains(&version),
+        }
+    }
+}
+
+/// 412 Precondition Failed with the current representation and its ETag,
+/// so the client can merge and retry without another round trip.
+pub fn precondition_failed<T: Serialize>(version: i32, current: &T) -> HttpResponse {
+    HttpResponse::PreconditionFailed()
+        .insert_header((header::ETAG, etag(version)))
+        .json(current)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn if_match(value: Option<&str>) -> Result<IfMatch, AppError> {
+        let mut req = TestRequest::default();
+        if let Some(value) = value {
+            req = req.insert_header((header::IF_MATCH, value));
+        }
+        IfMatch::from_request(&req.to_http_request())
+    }
+
+    #[test]
+    fn etag_is_the_quoted_version() {
+        assert_eq!(etag(7), "\"7\"");
+    }
+
+    #[test]
+    fn missing_or_blank_header_is_required() {
+        for value in [None, Some(""), Some("   ")] {
+            let error = if_match(value).err().expect("If-Match should be required");
+            assert_eq!(error.error_type, "PRECONDITION_REQUIRED");
+        }
+    }
+
+    #[test]
+    fn star_matches_any_version() {
+        let parsed = if_match(Some(" * ")).unwrap();
+        assert!(parsed.matches(1) && parsed.matches(42));
+        assert_eq!(parsed.versions(), None);
+    }
+
+    #[test]
+    fn lists_strong_and_weak_tags() {
+        let parsed = if_match(Some(r#""3", W/"5" ,"8""#)).unwrap();
+        assert_eq!(parsed.versions(), Some(&[3, 5, 8][..]));
+        assert!(parsed.matches(5));
+        assert!(!parsed.matches(4));
+    }
+
+    #[test]
+    fn unparseable_tags_match_nothing() {
+        let parsed = if_match(Some(r#""abc", W/"", "2""#)).unwrap();
+        assert_eq!(parsed.versions(), Some(&[2][..]));
+
+        let parsed = if_match(Some(r#""not-a-version""#)).unwrap();
+        assert_eq!(parsed.versions(), Some(&[][..]));
+        assert!(!parsed.matches(1));
+    }
+}
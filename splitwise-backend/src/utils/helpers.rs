@@ -1,3 +1,5 @@
+use actix_web::HttpRequest;
+use std::net::{IpAddr, SocketAddr};
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
 use regex::Regex;
@@ -20,8 +22,84 @@ pub fn validate_password(password: &str) -> bool {
     password.len() >= 8
 }
 
-pub fn format_currency(amount: f64) -> String {
-    format!("${:.2}", amount)
+/// Escapes `%`, `_` and `\` so user input matches literally in a LIKE pattern.
+pub fn escape_like(input: &str) -> String {
+    let mut escaped = String::with_capacity(input.len());
+    for c in input.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Escapes text for safe inclusion in HTML.
+pub fn escape_html(input: &str) -> String {
+    let mut escaped = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Formats an amount for display in the given ISO currency and locale, e.g.
+/// `$1,234.50` (en-US), `1.234,50 €` (de-DE) or `₹12,34,567.00` (en-IN).
+/// Unknown currencies fall back to their code as the symbol.
+pub fn format_currency(amount: f64, currency: &str, locale: &str) -> String {
+    let (symbol, decimals) = match currency {
+        "USD" | "CAD" | "AUD" => ("$", 2),
+        "EUR" => ("€", 2),
+        "GBP" => ("£", 2),
+        "INR" => ("₹", 2),
+        "JPY" => ("¥", 0),
+        other => (other, 2),
+    };
+    let language = locale.split('-').next().unwrap_or("en");
+    let (group_separator, decimal_separator, symbol_after) = match language {
+        "de" | "es" | "it" | "da" | "tr" => (".", ",", true),
+        "fr" | "sv" | "nb" | "fi" | "pl" | "cs" | "ru" => ("\u{a0}", ",", true),
+        _ => (",", ".", false),
+    };
+
+    let formatted = format!("{:.*}", decimals, amount.abs());
+    let (integer, fraction) = formatted.split_once('.').unwrap_or((&formatted, ""));
+
+    // Indian grouping keeps the last three digits together, then pairs (lakh/crore)
+    let mut groups = Vec::new();
+    let mut rest = integer;
+    let mut group_size = 3;
+    while rest.len() > group_size {
+        let (head, tail) = rest.split_at(rest.len() - group_size);
+        groups.push(tail);
+        rest = head;
+        if locale.ends_with("-IN") {
+            group_size = 2;
+        }
+    }
+    groups.push(rest);
+    groups.reverse();
+
+    let mut number = groups.join(group_separator);
+    if !fraction.is_empty() {
+        number.push_str(decimal_separator);
+        number.push_str(fraction);
+    }
+
+    let sign = if amount < 0.0 && formatted.chars().any(|c| c != '0' && c != '.') { "-" } else { "" };
+    let spacer = if symbol.chars().all(|c| c.is_ascii_alphabetic()) { " " } else { "" };
+    if symbol_after {
+        format!("{}{} {}", sign, number, symbol)
+    } else {
+        format!("{}{}{}{}", sign, symbol, spacer, number)
+    }
 }
 
 pub fn calculate_equal_split(total_amount: f64, num_people: usize) -> f64 {
@@ -30,4 +108,29 @@ pub fn calculate_equal_split(total_amount: f64, num_people: usize) -> f64 {
 
 pub fn calculate_percentage_split(total_amount: f64, percentage: f64) -> f64 {
     (total_amount * percentage / 100.0 * 100.0).round() / 100.0
-} 
\ No newline at end of file
+}
+
+/// Best-effort client IP address. `X-Forwarded-For`/`Forwarded` are only
+/// honoured when the server is configured to sit behind a trusted proxy,
+/// since clients can set them to anything. Only the address is returned,
+/// never a port, so one client keeps the same value across connections.
+pub fn client_ip(req: &HttpRequest, trust_proxy_headers: bool) -> Option<String> {
+    let forwarded = if trust_proxy_headers {
+        // Falls back to the peer address, port included, without a header
+        req.connection_info().realip_remote_addr().and_then(parse_ip)
+    } else {
+        None
+    };
+    forwarded
+        .or_else(|| req.peer_addr().map(|addr| addr.ip()))
+        .map(|ip| ip.to_string())
+}
+
+/// Accepts "1.2.3.4", "1.2.3.4:5678", "::1", "[::1]" and "[::1]:5678".
+fn parse_ip(addr: &str) -> Option<IpAddr> {
+    let addr = addr.trim();
+    addr.parse::<SocketAddr>()
+        .map(|addr| addr.ip())
+        .or_else(|_| addr.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>())
+        .ok()
+}
@@ -0,0 +1,147 @@
+use actix_web::body::{to_bytes, BoxBody, MessageBody};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::{header, Method, StatusCode};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpMessage, HttpResponse};
+use futures::StreamExt;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::config::Config;
+use crate::handlers::attachment_handler::MAX_ATTACHMENT_BODY_BYTES;
+use crate::models::idempotency_key::IdempotencyKey;
+use crate::utils::auth::identify;
+use crate::utils::error::AppError;
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+/// Set on responses that were replayed rather than produced by the handler.
+pub const IDEMPOTENT_REPLAYED_HEADER: &str = "Idempotent-Replayed";
+const MAX_KEY_LEN: usize = 255;
+
+/// Middleware making authenticated POSTs that carry an `Idempotency-Key`
+/// safe to retry. The first request with a key runs as usual and its
+/// response is stored; a later request with the same key and the same
+/// method, path and body gets that response back without running again.
+/// Reusing a key for a different request is rejected with 422, and a retry
+/// that arrives while the first request is still running gets 409. Server
+/// errors aren't stored, so the request can be retried with the same key.
+pub async fn idempotency(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let key = match req.headers().get(IDEMPOTENCY_KEY_HEADER) {
+        Some(key) if req.method() == Method::POST => key
+            .to_str()
+            .ok()
+            .filter(|key| !key.is_empty() && key.len() <= MAX_KEY_LEN)
+            .map(str::to_string)
+            .ok_or_else(|| AppError::bad_request("Idempotency-Key must be 1 to 255 visible ASCII characters"))?,
+        _ => return Ok(next.call(req).await?.map_into_boxed_body()),
+    };
+    let (Some(pool), Some(config)) = (
+        req.app_data::<web::Data<PgPool>>().cloned(),
+        req.app_data::<web::Data<Config>>().cloned(),
+    ) else {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    };
+    // Keys are per user. Anonymous requests (logging in, signing up) run
+    // without one and fail authentication in the handler as they would anyway
+    let Ok(user_id) = identify(req.request(), &pool).await else {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    };
+
+    let body = read_body(&mut req).await?;
+    let fingerprint = fingerprint(&req, &body);
+    req.set_payload(Payload::from(body));
+
+    if !IdempotencyKey::claim(&pool, user_id, &key, &fingerprint, config.idempotency_key_ttl_secs)
+        .await
+        .map_err(AppError::from)?
+    {
+        let response = replay(&pool, user_id, &key, &fingerprint).await?;
+        return Ok(req.into_response(response));
+    }
+
+    let res = match next.call(req).await {
+        Ok(res) => res.map_into_boxed_body(),
+        Err(e) => {
+            release(&pool, user_id, &key).await;
+            return Err(e);
+        }
+    };
+    if res.status().is_server_error() {
+        release(&pool, user_id, &key).await;
+        return Ok(res);
+    }
+
+    let (http_req, res) = res.into_parts();
+    let (res, body) = res.into_parts();
+    let body = match to_bytes(body).await {
+        Ok(body) => body,
+        Err(_) => {
+            release(&pool, user_id, &key).await;
+            return Err(AppError::internal_error("Failed to read the response").into());
+        }
+    };
+    let content_type = res.headers().get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok());
+    if let Err(e) = IdempotencyKey::complete(&pool, user_id, &key, res.status().as_u16(), content_type, &body).await {
+        // Better a retry that runs again than a key stuck in progress
+        log::error!("Failed to store the response for an idempotency key: {}", e);
+        release(&pool, user_id, &key).await;
+    }
+    Ok(ServiceResponse::new(http_req, res.set_body(body)).map_into_boxed_body())
+}
+
+/// Buffers the request body so it can be fingerprinted and then handed on.
+/// Capped at the largest body any endpoint accepts.
+async fn read_body(req: &mut ServiceRequest) -> Result<web::Bytes, AppError> {
+    let mut payload = req.take_payload();
+    let mut body = web::BytesMut::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk.map_err(|_| AppError::bad_request("Failed to read the request body"))?;
+        if body.len() + chunk.len() > MAX_ATTACHMENT_BODY_BYTES {
+            return Err(AppError::bad_request("Request body is too large"));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body.freeze())
+}
+
+fn fingerprint(req: &ServiceRequest, body: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(req.method().as_str());
+    hasher.update(b"\n");
+    hasher.update(req.uri().to_string());
+    hasher.update(b"\n");
+    hasher.update(body);
+    hex::encode(hasher.finalize())
+}
+
+/// The response for a key that's already taken.
+async fn replay(pool: &PgPool, user_id: Uuid, key: &str, fingerprint: &str) -> Result<HttpResponse, AppError> {
+    let Some(stored) = IdempotencyKey::find(pool, user_id, key).await? else {
+        // Released or expired since the claim failed
+        return Err(AppError::conflict("A request with this Idempotency-Key is in progress; retry it shortly"));
+    };
+    if stored.fingerprint != fingerprint {
+        return Err(AppError::unprocessable_entity(
+            "This Idempotency-Key was already used for a different request",
+        ));
+    }
+    let (Some(status), Some(body)) = (stored.response_status, stored.response_body) else {
+        return Err(AppError::conflict("A request with this Idempotency-Key is in progress; retry it shortly"));
+    };
+    let status = StatusCode::from_u16(status as u16)
+        .map_err(|_| AppError::internal_error("Stored response has an invalid status"))?;
+    let mut response = HttpResponse::build(status);
+    if let Some(content_type) = stored.response_content_type {
+        response.insert_header((header::CONTENT_TYPE, content_type));
+    }
+    Ok(response.insert_header((IDEMPOTENT_REPLAYED_HEADER, "true")).body(body))
+}
+
+async fn release(pool: &PgPool, user_id: Uuid, key: &str) {
+    if let Err(e) = IdempotencyKey::release(pool, user_id, key).await {
+        log::error!("Failed to release an idempotency key: {}", e);
+    }
+}
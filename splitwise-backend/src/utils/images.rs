@@ -0,0 +1,113 @@
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat, ImageReader, Limits};
+use std::io::Cursor;
+use uuid::Uuid;
+use crate::utils::auth::generate_token;
+
+pub const MAX_IMAGE_BYTES: usize = 5 * 1024 * 1024;
+pub const ALLOWED_IMAGE_TYPES: &[&str] = &["image/png", "image/jpeg", "image/webp"];
+pub const PROCESSED_CONTENT_TYPE: &str = "image/jpeg";
+
+const FULL_SIZE: u32 = 512;
+const THUMBNAIL_SIZE: u32 = 128;
+// Receipts stay large enough to read; their thumbnails keep the aspect ratio
+const RECEIPT_SIZE: u32 = 2048;
+const RECEIPT_THUMBNAIL_SIZE: u32 = 256;
+// Guards against decompression bombs: a small file can claim huge dimensions
+const MAX_DIMENSION: u32 = 8192;
+const MAX_DECODE_BYTES: u64 = 256 * 1024 * 1024;
+const JPEG_QUALITY: u8 = 85;
+
+/// An uploaded picture re-encoded for serving. Re-encoding also strips
+/// metadata such as EXIF GPS coordinates from the original.
+pub struct ProcessedImage {
+    pub full: Vec<u8>,
+    pub thumbnail: Vec<u8>,
+}
+
+/// Validates an upload by its content rather than its declared type, then
+/// produces a picture that fits in 512x512 and a square 128x128 thumbnail.
+/// CPU-bound; call it from `web::block`.
+pub fn process_image(data: &[u8]) -> Result<ProcessedImage, String> {
+    let image = decode_image(data)?;
+    let full = if image.width() > FULL_SIZE || image.height() > FULL_SIZE {
+        image.resize(FULL_SIZE, FULL_SIZE, FilterType::Lanczos3)
+    } else {
+        image.clone()
+    };
+    let thumbnail = image.resize_to_fill(THUMBNAIL_SIZE, THUMBNAIL_SIZE, FilterType::Lanczos3);
+
+    Ok(ProcessedImage {
+        full: encode_jpeg(&full)?,
+        thumbnail: encode_jpeg(&thumbnail)?,
+    })
+}
+
+/// Like `process_image`, for photos of receipts: the picture only shrinks
+/// to fit 2048x2048 and the thumbnail fits 256x256 without cropping.
+pub fn process_receipt_image(data: &[u8]) -> Result<ProcessedImage, String> {
+    let image = decode_image(data)?;
+    let full = if image.width() > RECEIPT_SIZE || image.height() > RECEIPT_SIZE {
+        image.resize(RECEIPT_SIZE, RECEIPT_SIZE, FilterType::Lanczos3)
+    } else {
+        image.clone()
+    };
+    let thumbnail = image.thumbnail(RECEIPT_THUMBNAIL_SIZE, RECEIPT_THUMBNAIL_SIZE);
+
+    Ok(ProcessedImage {
+        full: encode_jpeg(&full)?,
+        thumbnail: encode_jpeg(&thumbnail)?,
+    })
+}
+
+fn decode_image(data: &[u8]) -> Result<DynamicImage, String> {
+    let mut reader = ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .map_err(|_| "Could not read image".to_string())?;
+    match reader.format() {
+        Some(ImageFormat::Png | ImageFormat::Jpeg | ImageFormat::WebP) => (),
+        _ => return Err("Image must be a PNG, JPEG or WebP file".to_string()),
+    }
+
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_DIMENSION);
+    limits.max_image_height = Some(MAX_DIMENSION);
+    limits.max_alloc = Some(MAX_DECODE_BYTES);
+    reader.limits(limits);
+    reader.decode().map_err(|_| "Image is corrupt or too large".to_string())
+}
+
+fn encode_jpeg(image: &DynamicImage) -> Result<Vec<u8>, String> {
+    let mut buffer = Vec::new();
+    JpegEncoder::new_with_quality(&mut buffer, JPEG_QUALITY)
+        .encode_image(&image.to_rgb8())
+        .map_err(|e| format!("Failed to encode image: {}", e))?;
+    Ok(buffer)
+}
+
+/// Which rendition of an avatar to store or serve.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AvatarSize {
+    Full,
+    Thumbnail,
+}
+
+/// A fresh key prefix for an avatar of `owner_id` (a user or group). Every
+/// upload gets a new prefix, so URLs change and caches never go stale.
+pub fn new_avatar_key(kind: &str, owner_id: Uuid) -> String {
+    format!("{}/{}/{}", kind, owner_id, &generate_token()[..16])
+}
+
+pub fn avatar_blob_key(avatar_key: &str, size: AvatarSize) -> String {
+    match size {
+        AvatarSize::Full => format!("{}/full.jpg", avatar_key),
+        AvatarSize::Thumbnail => format!("{}/thumb.jpg", avatar_key),
+    }
+}
+
+/// URL the avatar is served from; requests to it must be authenticated.
+pub fn avatar_url(kind: &str, owner_id: Uuid, avatar_key: Option<&str>) -> Option<String> {
+    let version = avatar_key?.rsplit('/').next()?;
+    Some(format!("/api/{}/{}/avatar?v={}", kind, owner_id, version))
+}
@@ -0,0 +1,153 @@
+use jsonwebtoken::jwk::{AlgorithmParameters, JwkSet};
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::fs;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use crate::config::Config;
+
+static KEYS: OnceLock<KeyStore> = OnceLock::new();
+
+/// Signing key plus every key tokens may currently be verified with.
+///
+/// Asymmetric keys are identified by the `kid` header. Rotating works by
+/// publishing the new public key in the JWKS file, switching
+/// `JWT_SIGNING_KEY_ID`/`JWT_SIGNING_KEY_FILE` to it, and removing the old
+/// public key once the last token it signed has expired. Tokens without a
+/// `kid` are legacy HS256 tokens and are accepted while `JWT_SECRET` is set.
+pub struct KeyStore {
+    algorithm: Algorithm,
+    signing_kid: Option<String>,
+    signing_key: EncodingKey,
+    verification_keys: HashMap<String, (Algorithm, DecodingKey)>,
+    legacy_secret: Option<DecodingKey>,
+    jwks: JwkSet,
+}
+
+/// Loads the keys described by the configuration. Called once at startup so
+/// a bad key file stops the server instead of failing the first login.
+pub fn init(config: &Config) -> Result<(), String> {
+    let store = KeyStore::from_config(config)?;
+    KEYS.set(store).map_err(|_| "JWT keys are already initialised".to_string())
+}
+
+pub fn keys() -> &'static KeyStore {
+    KEYS.get().expect("JWT keys are not initialised; call jwt_keys::init at startup")
+}
+
+impl KeyStore {
+    fn from_config(config: &Config) -> Result<KeyStore, String> {
+        let algorithm = Algorithm::from_str(&config.jwt_algorithm)
+            .map_err(|_| format!("Unknown JWT_ALGORITHM {}", config.jwt_algorithm))?;
+
+        let jwks = match &config.jwt_jwks_file {
+            Some(path) => {
+                let contents = fs::read_to_string(path)
+                    .map_err(|e| format!("Failed to read JWT_JWKS_FILE {}: {}", path, e))?;
+                serde_json::from_str::<JwkSet>(&contents)
+                    .map_err(|e| format!("Invalid JWKS in {}: {}", path, e))?
+            }
+            None => JwkSet { keys: Vec::new() },
+        };
+
+        let mut verification_keys = HashMap::new();
+        for jwk in &jwks.keys {
+            let kid = jwk.common.key_id.clone().ok_or("Every JWKS key needs a kid")?;
+            // The JWKS is served publicly, so symmetric ("oct") keys must never be in it
+            let key_algorithm = match &jwk.algorithm {
+                AlgorithmParameters::RSA(_) => match jwk.common.algorithm {
+                    Some(alg @ (Algorithm::RS256 | Algorithm::RS384 | Algorithm::RS512)) => alg,
+                    None => Algorithm::RS256,
+                    Some(other) => return Err(format!("Key {} has unsupported algorithm {:?}", kid, other)),
+                },
+                AlgorithmParameters::OctetKeyPair(_) => Algorithm::EdDSA,
+                _ => return Err(format!("Key {} must be an RSA or Ed25519 public key", kid)),
+            };
+            let decoding_key = DecodingKey::from_jwk(jwk).map_err(|e| format!("Invalid key {}: {}", kid, e))?;
+            verification_keys.insert(kid, (key_algorithm, decoding_key));
+        }
+
+        let legacy_secret = config.jwt_secret.as_ref().map(|s| DecodingKey::from_secret(s.as_bytes()));
+
+        let (signing_kid, signing_key) = match algorithm {
+            Algorithm::HS256 => {
+                let secret = config.jwt_secret.as_ref().ok_or("JWT_SECRET is required for HS256")?;
+                (None, EncodingKey::from_secret(secret.as_bytes()))
+            }
+            Algorithm::RS256 | Algorithm::EdDSA => {
+                let kid = config
+                    .jwt_signing_key_id
+                    .clone()
+                    .ok_or("JWT_SIGNING_KEY_ID is required for asymmetric signing")?;
+                let path = config
+                    .jwt_signing_key_file
+                    .as_ref()
+                    .ok_or("JWT_SIGNING_KEY_FILE is required for asymmetric signing")?;
+                let pem = fs::read(path).map_err(|e| format!("Failed to read JWT_SIGNING_KEY_FILE {}: {}", path, e))?;
+                let key = if algorithm == Algorithm::RS256 {
+                    EncodingKey::from_rsa_pem(&pem)
+                } else {
+                    EncodingKey::from_ed_pem(&pem)
+                }
+                .map_err(|e| format!("Invalid signing key in {}: {}", path, e))?;
+
+                // Otherwise we'd hand out tokens that nothing can verify
+                match verification_keys.get(&kid) {
+                    Some((key_algorithm, _)) if *key_algorithm == algorithm => (),
+                    _ => return Err(format!("Signing key {} must be published in the JWKS as a {:?} key", kid, algorithm)),
+                }
+                (Some(kid), key)
+            }
+            other => return Err(format!("Unsupported JWT_ALGORITHM {:?}", other)),
+        };
+
+        Ok(KeyStore {
+            algorithm,
+            signing_kid,
+            signing_key,
+            verification_keys,
+            legacy_secret,
+            jwks,
+        })
+    }
+
+    pub fn header(&self) -> Header {
+        let mut header = Header::new(self.algorithm);
+        header.kid = self.signing_kid.clone();
+        header
+    }
+
+    pub fn signing_key(&self) -> &EncodingKey {
+        &self.signing_key
+    }
+
+    /// Public keys other services can use to verify our tokens.
+    pub fn jwks(&self) -> &JwkSet {
+        &self.jwks
+    }
+
+    /// Verifies a token against the key named by its `kid`. The algorithm is
+    /// pinned to the one the key was published for, never taken from the
+    /// token, so an RSA public key can't be abused as an HMAC secret.
+    pub fn decode<T: DeserializeOwned>(&self, token: &str) -> Result<T, jsonwebtoken::errors::Error> {
+        let header = decode_header(token)?;
+        let (algorithm, key) = match &header.kid {
+            Some(kid) => {
+                let (algorithm, key) = self
+                    .verification_keys
+                    .get(kid)
+                    .ok_or(jsonwebtoken::errors::ErrorKind::InvalidToken)?;
+                (*algorithm, key)
+            }
+            None => (
+                Algorithm::HS256,
+                self.legacy_secret.as_ref().ok_or(jsonwebtoken::errors::ErrorKind::InvalidToken)?,
+            ),
+        };
+        if header.alg != algorithm {
+            return Err(jsonwebtoken::errors::ErrorKind::InvalidAlgorithm.into());
+        }
+        Ok(decode::<T>(token, key, &Validation::new(algorithm))?.claims)
+    }
+}
@@ -0,0 +1,101 @@
+use async_trait::async_trait;
+use sqlx::PgPool;
+use std::fmt;
+use uuid::Uuid;
+
+#[derive(Debug, Clone)]
+pub struct EmailMessage {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[derive(Debug)]
+pub struct MailerError(pub String);
+
+impl fmt::Display for MailerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, message: EmailMessage) -> Result<(), MailerError>;
+}
+
+/// Writes outgoing mail to the application log instead of delivering it.
+/// Used in development and whenever no real transport is configured.
+pub struct LogMailer;
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, message: EmailMessage) -> Result<(), MailerError> {
+        log::info!(
+            "Sending email to {} with subject {:?}:\n{}",
+            message.to,
+            message.subject,
+            message.body
+        );
+        Ok(())
+    }
+}
+
+/// What a notification is about. Users can opt out of activity emails in
+/// their preferences; security notices are always sent.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NotificationKind {
+    Security,
+    GroupActivity,
+    ExpenseActivity,
+}
+
+/// Sends a notification email to a user, skipping accounts that have not
+/// verified their email address yet or have opted out of this kind.
+pub async fn notify_user(
+    pool: &PgPool,
+    mailer: &dyn Mailer,
+    user_id: Uuid,
+    kind: NotificationKind,
+    subject: &str,
+    body: &str,
+) -> Result<(), sqlx::Error> {
+    let recipient: Option<(String, bool, bool)> = sqlx::query_as(
+        r#"
+        SELECT u.email,
+               COALESCE(p.notify_group_activity, TRUE),
+               COALESCE(p.notify_expense_activity, TRUE)
+        FROM users u
+        LEFT JOIN user_preferences p ON p.user_id = u.id
+        WHERE u.id = $1 AND u.email_verified_at IS NOT NULL
+        "#,
+    )
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    let email = match recipient {
+        Some((email, group_activity, expense_activity)) => {
+            let wanted = match kind {
+                NotificationKind::Security => true,
+                NotificationKind::GroupActivity => group_activity,
+                NotificationKind::ExpenseActivity => expense_activity,
+            };
+            if !wanted {
+                return Ok(());
+            }
+            email
+        }
+        None => return Ok(()),
+    };
+
+    let message = EmailMessage {
+        to: email,
+        subject: subject.to_string(),
+        body: body.to_string(),
+    };
+    if let Err(e) = mailer.send(message).await {
+        log::error!("Failed to send notification to user {}: {}", user_id, e);
+    }
+    Ok(())
+}
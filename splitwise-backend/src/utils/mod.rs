@@ -1,3 +1,14 @@
 pub mod auth;
+pub mod blob_store;
 pub mod error;
-pub mod helpers; 
\ No newline at end of file
+pub mod etag;
+pub mod helpers;
+pub mod idempotency;
+pub mod images;
+pub mod jwt_keys;
+pub mod mailer;
+pub mod multipart;
+pub mod oidc;
+#[cfg(feature = "s3")]
+pub mod s3_blob_store;
+pub mod totp; 
\ No newline at end of file
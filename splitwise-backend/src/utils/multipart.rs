@@ -0,0 +1,64 @@
+use actix_web::{http::header, web, HttpRequest};
+use futures::stream;
+use std::convert::Infallible;
+use crate::utils::error::AppError;
+
+/// One file field of a multipart/form-data upload.
+pub struct UploadedFile {
+    pub file_name: Option<String>,
+    pub content_type: Option<String>, // As declared by the client; don't trust it
+    pub data: Vec<u8>,
+}
+
+/// Pulls up to `max_files` fields called `field_name` out of a buffered
+/// multipart/form-data body, rejecting any larger than `max_bytes`. The body
+/// is buffered (bounded by the route's `PayloadConfig`) because
+/// `web::Payload` can't be handed to multer across await points.
+pub async fn read_files(
+    req: &HttpRequest,
+    body: web::Bytes,
+    field_name: &str,
+    max_files: usize,
+    max_bytes: usize,
+) -> Result<Vec<UploadedFile>, AppError> {
+    let content_type = req
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    let boundary = multer::parse_boundary(content_type)
+        .map_err(|_| AppError::bad_request("Expected a multipart/form-data upload"))?;
+
+    let body = stream::once(async move { Ok::<_, Infallible>(body) });
+    let mut multipart = multer::Multipart::new(body, boundary);
+    let mut files = Vec::new();
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|_| AppError::bad_request("Malformed multipart upload"))?
+    {
+        if field.name() != Some(field_name) {
+            continue;
+        }
+        if files.len() == max_files {
+            return Err(AppError::bad_request(&format!("At most {} files can be uploaded at once", max_files)));
+        }
+        let file_name = field.file_name().map(str::to_string);
+        let content_type = field.content_type().map(|mime| mime.essence_str().to_string());
+        let data = field
+            .bytes()
+            .await
+            .map_err(|_| AppError::bad_request("Malformed multipart upload"))?;
+        if data.len() > max_bytes {
+            return Err(AppError::bad_request(&format!(
+                "Each file must be at most {} MB",
+                max_bytes / (1024 * 1024)
+            )));
+        }
+        files.push(UploadedFile { file_name, content_type, data: data.to_vec() });
+    }
+    if files.is_empty() {
+        return Err(AppError::bad_request(&format!("Missing {} field", field_name)));
+    }
+    Ok(files)
+}
@@ -0,0 +1,154 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use sha2::{Digest, Sha256};
+use url::Url;
+use crate::config::S3Config;
+use crate::utils::blob_store::{BlobError, BlobStore};
+
+// RFC 3986 unreserved characters stay as they are in SigV4 canonical URIs
+const URI_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'.').remove(b'_').remove(b'~');
+
+/// Blob store for S3 or any S3-compatible service (MinIO, R2, ...), using
+/// path-style URLs and AWS Signature Version 4.
+pub struct S3BlobStore {
+    config: S3Config,
+    endpoint: Url,
+    client: reqwest::Client,
+}
+
+impl From<reqwest::Error> for BlobError {
+    fn from(error: reqwest::Error) -> Self {
+        BlobError(error.to_string())
+    }
+}
+
+impl S3BlobStore {
+    pub fn new(config: S3Config) -> Result<Self, String> {
+        let endpoint = Url::parse(&config.endpoint).map_err(|e| format!("Invalid S3_ENDPOINT: {}", e))?;
+        Ok(S3BlobStore {
+            config,
+            endpoint,
+            client: reqwest::Client::new(),
+        })
+    }
+
+    fn object_path(&self, key: &str) -> String {
+        let mut path = format!("/{}", utf8_percent_encode(&self.config.bucket, URI_SEGMENT));
+        for segment in key.split('/') {
+            path.push('/');
+            path.push_str(&utf8_percent_encode(segment, URI_SEGMENT).to_string());
+        }
+        path
+    }
+
+    /// Builds a signed request for `key`. Only the headers that are signed
+    /// are set, so the request matches its signature exactly.
+    fn signed_request(
+        &self,
+        method: reqwest::Method,
+        key: &str,
+        content_type: Option<&str>,
+        payload: &[u8],
+    ) -> Result<reqwest::RequestBuilder, BlobError> {
+        let path = self.object_path(key);
+        let mut url = self.endpoint.clone();
+        url.set_path(&path);
+        let host = match (url.host_str(), url.port()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_string(),
+            (None, _) => return Err(BlobError("S3_ENDPOINT has no host".to_string())),
+        };
+
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let payload_hash = hex::encode(Sha256::digest(payload));
+
+        let mut headers = vec![
+            ("host", host),
+            ("x-amz-content-sha256", payload_hash.clone()),
+            ("x-amz-date", amz_date.clone()),
+        ];
+        if let Some(content_type) = content_type {
+            headers.push(("content-type", content_type.to_string()));
+        }
+        headers.sort_by(|a, b| a.0.cmp(b.0));
+
+        let canonical_headers: String = headers.iter().map(|(name, value)| format!("{}:{}\n", name, value.trim())).collect();
+        let signed_headers = headers.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(";");
+        let canonical_request = format!(
+            "{}\n{}\n\n{}\n{}\n{}",
+            method.as_str(),
+            path,
+            canonical_headers,
+            signed_headers,
+            payload_hash
+        );
+
+        let scope = format!("{}/{}/s3/aws4_request", date, self.config.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let secret = format!("AWS4{}", self.config.secret_access_key);
+        let mut signing_key = hmac_sha256(secret.as_bytes(), date.as_bytes());
+        for part in [self.config.region.as_str(), "s3", "aws4_request"] {
+            signing_key = hmac_sha256(&signing_key, part.as_bytes());
+        }
+        let signature = hex::encode(hmac_sha256(&signing_key, string_to_sign.as_bytes()));
+
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.config.access_key_id, scope, signed_headers, signature
+        );
+
+        let mut request = self.client.request(method, url).header("authorization", authorization);
+        for (name, value) in headers.into_iter().filter(|(name, _)| *name != "host") {
+            request = request.header(name, value);
+        }
+        Ok(request)
+    }
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+#[async_trait]
+impl BlobStore for S3BlobStore {
+    async fn put(&self, key: &str, content_type: &str, data: Vec<u8>) -> Result<(), BlobError> {
+        let response = self
+            .signed_request(reqwest::Method::PUT, key, Some(content_type), &data)?
+            .body(data)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(BlobError(format!("S3 PUT {} returned {}", key, response.status())));
+        }
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, BlobError> {
+        let response = self.signed_request(reqwest::Method::GET, key, None, b"")?.send().await?;
+        match response.status() {
+            reqwest::StatusCode::NOT_FOUND => Ok(None),
+            status if status.is_success() => Ok(Some(response.bytes().await?.to_vec())),
+            status => Err(BlobError(format!("S3 GET {} returned {}", key, status))),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), BlobError> {
+        let response = self.signed_request(reqwest::Method::DELETE, key, None, b"")?.send().await?;
+        if !response.status().is_success() && response.status() != reqwest::StatusCode::NOT_FOUND {
+            return Err(BlobError(format!("S3 DELETE {} returned {}", key, response.status())));
+        }
+        Ok(())
+    }
+}